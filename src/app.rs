@@ -0,0 +1,112 @@
+use std::{ops::Deref, sync::Arc};
+
+use crate::{
+    backend::Backend,
+    events::{self, EventAttributes, EventError},
+    transaction::State,
+    Transaction,
+};
+
+/// A handle to the New Relic app used by the `NewRelic` fairing.
+///
+/// The fairing adds this to Rocket's managed state when it's attached, so
+/// handlers can use `State<NewRelicApp>` to instrument work done outside
+/// of requests, such as background jobs, with the same app and settings.
+/// Fairings attached afterwards can get it with
+/// `rocket.state::<NewRelicApp>()`. Clones share the same app, so there's
+/// only ever one connection to the daemon.
+///
+/// The handle is managed even if the fairing is disabled, in which case
+/// nothing it records is sent. If more than one `NewRelic` fairing is
+/// attached, only the first one's handle is managed.
+///
+/// New Relic records custom metrics as part of a transaction, so outside
+/// of requests use a background transaction:
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use newrelic_fairing::NewRelicApp;
+/// use rocket::State;
+///
+/// fn refresh_cache(app: State<NewRelicApp>) {
+///     let transaction = app.background_transaction("refresh_cache");
+///     transaction.record_custom_metric("cache/refresh", Duration::from_millis(12));
+/// }
+/// ```
+#[derive(Clone)]
+pub struct NewRelicApp(Option<Arc<dyn Backend>>);
+
+impl NewRelicApp {
+    pub(crate) fn new(backend: Option<Arc<dyn Backend>>) -> Self {
+        NewRelicApp(backend)
+    }
+
+    /// Whether the app records any transactions.
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub(crate) fn backend(&self) -> Option<&dyn Backend> {
+        self.0.as_ref().map(|backend| &**backend)
+    }
+
+    /// Start a transaction for work which isn't a web request, such as a
+    /// background job, cron task or queue consumer.
+    ///
+    /// The transaction ends when it's dropped, or when `end` is called.
+    pub fn background_transaction(&self, name: &str) -> BackgroundTransaction {
+        let transaction = match self.backend() {
+            Some(backend) => Transaction::background(backend, name),
+            None => Transaction::none(),
+        };
+        BackgroundTransaction(transaction)
+    }
+
+    /// Record a custom event outside of a request.
+    ///
+    /// New Relic records events as part of a transaction, so this records
+    /// the event in a background transaction named `CustomEvent/<type>`.
+    /// Each call sends a whole non-web transaction to New Relic, which
+    /// shows up in its transaction lists and counts towards its limits, so
+    /// when recording events from a background job, record them on the
+    /// job's `BackgroundTransaction` instead. Within a request, use
+    /// `Transaction::record_custom_event`.
+    pub fn record_custom_event(
+        &self,
+        event_type: &str,
+        attributes: &EventAttributes,
+    ) -> Result<(), EventError> {
+        // Checked first, so that invalid events don't start a transaction.
+        events::validate(event_type, attributes)?;
+        self.background_transaction(&format!("CustomEvent/{}", event_type))
+            .record_custom_event(event_type, attributes)
+    }
+}
+
+/// A transaction for work which isn't a web request.
+///
+/// This can be used just like the `Transaction` for a request, e.g. to add
+/// attributes and segments, and is always sent to New Relic.
+pub struct BackgroundTransaction(Transaction);
+
+impl BackgroundTransaction {
+    /// End the transaction now, rather than when it's dropped.
+    pub fn end(self) {}
+}
+
+impl Deref for BackgroundTransaction {
+    type Target = Transaction;
+
+    fn deref(&self) -> &Transaction {
+        &self.0
+    }
+}
+
+impl Drop for BackgroundTransaction {
+    fn drop(&mut self) {
+        if let State::Traced(transaction) = self.0.take() {
+            transaction.end();
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::{error, fmt};
+
+// New Relic's limits on custom attributes.
+const MAX_KEY_LENGTH: usize = 255;
+const MAX_VALUE_LENGTH: usize = 255;
+const MAX_ATTRIBUTES: usize = 64;
+
+// The fairing adds up to 13 attributes of its own once the response is
+// ready (see `web`, `trace_context` and `timing`), which count towards
+// New Relic's limit too.
+const RESERVED_ATTRIBUTES: usize = 13;
+pub(crate) const MAX_CUSTOM_ATTRIBUTES: usize = MAX_ATTRIBUTES - RESERVED_ATTRIBUTES;
+
+// Prefixes of the keys used by the fairing's own attributes, which would
+// overwrite custom attributes with the same keys.
+const RESERVED_PREFIXES: &[&str] = &["request.", "response.", "http.", "w3c.", "timing."];
+
+/// The value of a transaction attribute.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Attribute {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl Attribute {
+    // Check a custom attribute against New Relic's limits.
+    pub(crate) fn validate(&self, key: &str) -> Result<(), AttributeError> {
+        if key.is_empty() {
+            return Err(AttributeError::EmptyKey);
+        }
+        if key.len() > MAX_KEY_LENGTH {
+            return Err(AttributeError::KeyTooLong(key.to_string()));
+        }
+        if RESERVED_PREFIXES
+            .iter()
+            .any(|prefix| key.starts_with(prefix))
+        {
+            return Err(AttributeError::ReservedKey(key.to_string()));
+        }
+        match self {
+            Attribute::String(s) if s.len() > MAX_VALUE_LENGTH => {
+                Err(AttributeError::ValueTooLong(key.to_string()))
+            }
+            Attribute::Float(f) if !f.is_finite() => {
+                Err(AttributeError::NotFinite(key.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A custom attribute which New Relic would reject.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttributeError {
+    EmptyKey,
+    /// The key was longer than 255 bytes.
+    KeyTooLong(String),
+    /// The key starts with `request.`, `response.`, `http.`, `w3c.` or
+    /// `timing.`, which are used by the fairing's own attributes.
+    ReservedKey(String),
+    /// The value of the attribute with this key was longer than 255 bytes.
+    ValueTooLong(String),
+    /// The value of the attribute with this key was NaN or infinite.
+    NotFinite(String),
+    /// The transaction already has 51 custom attributes, leaving room for
+    /// the attributes added by the fairing within New Relic's limit of 64.
+    TooManyAttributes,
+}
+
+impl fmt::Display for AttributeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttributeError::EmptyKey => write!(f, "attribute key is empty"),
+            AttributeError::KeyTooLong(key) => {
+                write!(
+                    f,
+                    "attribute key {:?} is longer than {} bytes",
+                    key, MAX_KEY_LENGTH
+                )
+            }
+            AttributeError::ReservedKey(key) => {
+                write!(f, "attribute key {:?} is reserved by the fairing", key)
+            }
+            AttributeError::ValueTooLong(key) => write!(
+                f,
+                "value of attribute {:?} is longer than {} bytes",
+                key, MAX_VALUE_LENGTH
+            ),
+            AttributeError::NotFinite(key) => {
+                write!(f, "value of attribute {:?} is not finite", key)
+            }
+            AttributeError::TooManyAttributes => write!(
+                f,
+                "transaction already has {} custom attributes",
+                MAX_CUSTOM_ATTRIBUTES
+            ),
+        }
+    }
+}
+
+impl error::Error for AttributeError {}
+
+impl From<String> for Attribute {
+    fn from(value: String) -> Self {
+        Attribute::String(value)
+    }
+}
+
+impl<'a> From<&'a str> for Attribute {
+    fn from(value: &'a str) -> Self {
+        Attribute::String(value.to_string())
+    }
+}
+
+impl From<i32> for Attribute {
+    fn from(value: i32) -> Self {
+        Attribute::Int(value.into())
+    }
+}
+
+impl From<i64> for Attribute {
+    fn from(value: i64) -> Self {
+        Attribute::Int(value)
+    }
+}
+
+impl From<u32> for Attribute {
+    fn from(value: u32) -> Self {
+        Attribute::Int(value.into())
+    }
+}
+
+impl From<f64> for Attribute {
+    fn from(value: f64) -> Self {
+        Attribute::Float(value)
+    }
+}
+
+impl From<bool> for Attribute {
+    fn from(value: bool) -> Self {
+        Attribute::Bool(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_attributes() {
+        assert_eq!(Attribute::from("acme").validate("tenant"), Ok(()));
+        assert_eq!(Attribute::from(3).validate("items"), Ok(()));
+        assert_eq!(Attribute::from(0.5).validate("ratio"), Ok(()));
+        assert_eq!(Attribute::from(true).validate("beta"), Ok(()));
+        assert_eq!(
+            Attribute::from("v".repeat(MAX_VALUE_LENGTH)).validate(&"k".repeat(MAX_KEY_LENGTH)),
+            Ok(())
+        );
+        // Only the fairing's prefixes are reserved.
+        assert_eq!(Attribute::from(1).validate("requests"), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        assert_eq!(
+            Attribute::from(1).validate(""),
+            Err(AttributeError::EmptyKey)
+        );
+
+        let key = "k".repeat(MAX_KEY_LENGTH + 1);
+        assert_eq!(
+            Attribute::from(1).validate(&key),
+            Err(AttributeError::KeyTooLong(key))
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_keys() {
+        for key in &[
+            "request.method",
+            "request.headers.host",
+            "response.headers.contentLength",
+            "http.statusCode",
+            "w3c.traceId",
+            "timing.handler",
+        ] {
+            assert_eq!(
+                Attribute::from("value").validate(key),
+                Err(AttributeError::ReservedKey(key.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert_eq!(
+            Attribute::from("v".repeat(MAX_VALUE_LENGTH + 1)).validate("key"),
+            Err(AttributeError::ValueTooLong("key".to_string()))
+        );
+        for value in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(
+                Attribute::from(*value).validate("key"),
+                Err(AttributeError::NotFinite("key".to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Telemetry backends that the `NewRelic` fairing reports to.
+//!
+//! The New Relic SDK is the default backend. The recording backend keeps
+//! transactions in memory instead, so that instrumentation can be tested
+//! without a New Relic daemon.
+
+use std::time::Duration;
+
+use crate::{distributed_tracing::InboundTrace, Attribute};
+
+mod recording;
+mod sdk;
+
+pub use self::recording::{
+    RecordedError, RecordedEvent, RecordedSegment, RecordedTransaction, RecordingBackend,
+    TransactionKind, TransactionOutcome,
+};
+pub use self::sdk::NewRelicBackend;
+
+/// A telemetry backend, able to start transactions.
+pub trait Backend: Send + Sync + 'static {
+    /// Start a new web transaction with the given name.
+    ///
+    /// Returns `None` if the backend couldn't start the transaction.
+    fn start_web_transaction(&self, name: &str) -> Option<Box<dyn BackendTransaction>>;
+
+    /// Start a new transaction for work which isn't a web request.
+    ///
+    /// Returns `None` if the backend couldn't start the transaction.
+    fn start_background_transaction(&self, name: &str) -> Option<Box<dyn BackendTransaction>>;
+}
+
+/// A running transaction in a telemetry backend.
+pub trait BackendTransaction: Send {
+    /// End the transaction, sending it to the backend.
+    fn end(self: Box<Self>);
+
+    /// Ignore the transaction, so that it is never sent to the backend.
+    fn ignore(self: Box<Self>);
+
+    /// Change the name of the transaction.
+    fn set_name(&mut self, name: &str);
+
+    /// Record an error on the transaction.
+    fn notice_error(&mut self, priority: i32, message: &str, class: &str);
+
+    /// Add an attribute to the transaction.
+    fn add_attribute(&mut self, key: &str, value: &Attribute);
+
+    /// Record a custom metric as part of the transaction.
+    fn record_custom_metric(&mut self, name: &str, value: Duration);
+
+    /// Record a custom event as part of the transaction.
+    fn record_custom_event(&mut self, event_type: &str, attributes: &[(String, Attribute)]);
+
+    /// Start a new segment, optionally nested inside another segment.
+    ///
+    /// Returns `None` if the backend couldn't start the segment.
+    fn start_segment(
+        &mut self,
+        params: &SegmentParams,
+        parent: Option<SegmentId>,
+    ) -> Option<SegmentId>;
+
+    /// End a segment, and any segments still open inside it.
+    fn end_segment(&mut self, id: SegmentId);
+
+    /// Continue a distributed trace from an upstream service.
+    fn accept_distributed_trace(&mut self, trace: &InboundTrace);
+
+    /// Create a distributed trace payload for an outgoing request, made
+    /// from the given segment or from the transaction itself.
+    ///
+    /// The payload is sent in the `newrelic` header.
+    fn distributed_trace_payload(&mut self, segment: Option<SegmentId>) -> Option<String>;
+}
+
+/// Identifies a segment within a single backend transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SegmentId(pub u64);
+
+/// The kind of segment to start, and its details.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SegmentParams {
+    /// A custom segment, with a name and a category.
+    Custom { name: String, category: String },
+
+    /// A call to a datastore, such as a database query.
+    Datastore {
+        /// The datastore product, e.g. `Postgres` or `Redis`.
+        product: String,
+        /// The table or collection being operated on, if any.
+        collection: Option<String>,
+        /// The operation, e.g. `select` or `get`.
+        operation: String,
+        /// The query, without any bound parameters.
+        query: Option<String>,
+    },
+
+    /// A call to an external service, such as an HTTP request.
+    External {
+        /// The URI called, without credentials, query string or fragment.
+        uri: String,
+        /// The procedure, e.g. the HTTP method.
+        procedure: Option<String>,
+        /// The library used to make the call, e.g. `reqwest`.
+        library: Option<String>,
+    },
+}
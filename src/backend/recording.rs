@@ -0,0 +1,484 @@
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use super::{Backend, BackendTransaction, SegmentId, SegmentParams};
+use crate::{distributed_tracing::InboundTrace, Attribute};
+
+/// A backend which records transactions in memory.
+///
+/// Clones share the same recorded transactions, so a clone can be kept
+/// to inspect the transactions produced by a fairing using the backend:
+///
+/// ```
+/// use newrelic_fairing::{backend::RecordingBackend, NewRelic};
+/// use rocket::local::Client;
+///
+/// let backend = RecordingBackend::new();
+/// let rocket = rocket::ignite().attach(NewRelic::with_backend(backend.clone()));
+/// let client = Client::new(rocket).unwrap();
+///
+/// client.get("/").dispatch();
+///
+/// backend.assert_recorded("catcher/404").assert_ignored();
+/// ```
+#[derive(Clone, Default)]
+pub struct RecordingBackend {
+    transactions: Arc<Mutex<Vec<RecordedTransaction>>>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All transactions which have finished, in the order they finished.
+    pub fn transactions(&self) -> Vec<RecordedTransaction> {
+        self.transactions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// The most recently finished transaction with the given name.
+    pub fn find(&self, name: &str) -> Option<RecordedTransaction> {
+        self.find_all(name).pop()
+    }
+
+    /// All finished transactions with the given name.
+    pub fn find_all(&self, name: &str) -> Vec<RecordedTransaction> {
+        self.transactions()
+            .into_iter()
+            .filter(|t| t.name == name)
+            .collect()
+    }
+
+    /// The most recently finished transaction with the given name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no transaction with that name has finished.
+    pub fn assert_recorded(&self, name: &str) -> RecordedTransaction {
+        match self.find(name) {
+            Some(transaction) => transaction,
+            None => panic!(
+                "expected a transaction named {:?}, but only found {:?}",
+                name,
+                self.names()
+            ),
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if a transaction with the given name has finished.
+    pub fn assert_not_recorded(&self, name: &str) {
+        if self.find(name).is_some() {
+            panic!("expected no transaction named {:?}, but found one", name);
+        }
+    }
+
+    // The names of all finished transactions.
+    fn names(&self) -> Vec<String> {
+        self.transactions().into_iter().map(|t| t.name).collect()
+    }
+
+    /// Forget all recorded transactions.
+    pub fn clear(&self) {
+        self.transactions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    fn record(&self, transaction: RecordedTransaction) {
+        self.transactions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(transaction);
+    }
+}
+
+impl Backend for RecordingBackend {
+    fn start_web_transaction(&self, name: &str) -> Option<Box<dyn BackendTransaction>> {
+        Some(self.start_transaction(name, TransactionKind::Web))
+    }
+
+    fn start_background_transaction(&self, name: &str) -> Option<Box<dyn BackendTransaction>> {
+        Some(self.start_transaction(name, TransactionKind::Background))
+    }
+}
+
+impl RecordingBackend {
+    fn start_transaction(&self, name: &str, kind: TransactionKind) -> Box<dyn BackendTransaction> {
+        Box::new(RecordingTransaction {
+            backend: self.clone(),
+            transaction: Some(RecordedTransaction {
+                name: name.to_string(),
+                kind,
+                outcome: TransactionOutcome::Ended,
+                duration: Duration::default(),
+                errors: Vec::new(),
+                attributes: Vec::new(),
+                metrics: Vec::new(),
+                events: Vec::new(),
+                segments: Vec::new(),
+                inbound_trace: None,
+            }),
+            started: Instant::now(),
+            open_segments: Vec::new(),
+        })
+    }
+}
+
+/// A transaction recorded by the `RecordingBackend`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedTransaction {
+    pub name: String,
+    pub kind: TransactionKind,
+    pub outcome: TransactionOutcome,
+    /// How long the transaction ran for, from starting to finishing.
+    pub duration: Duration,
+    pub errors: Vec<RecordedError>,
+    pub attributes: Vec<(String, Attribute)>,
+    pub metrics: Vec<(String, Duration)>,
+    pub events: Vec<RecordedEvent>,
+    /// Segments, in the order they were started.
+    pub segments: Vec<RecordedSegment>,
+    /// The distributed trace the transaction continued, if any.
+    pub inbound_trace: Option<InboundTrace>,
+}
+
+impl RecordedTransaction {
+    /// The most recent value of an attribute, if it was added.
+    pub fn attribute(&self, key: &str) -> Option<&Attribute> {
+        self.attributes
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// The first segment with the given name, if any.
+    pub fn segment(&self, name: &str) -> Option<&RecordedSegment> {
+        self.segments.iter().find(|s| s.name() == name)
+    }
+
+    /// Whether an error was noticed for the given HTTP status code.
+    ///
+    /// Errors reported with a class of their own, such as those attached
+    /// by handlers, don't have a status code; use `assert_error_class`.
+    pub fn has_error_status(&self, status: u16) -> bool {
+        self.errors.iter().any(|e| e.status() == Some(status))
+    }
+
+    /// # Panics
+    ///
+    /// Panics unless the transaction was ended.
+    pub fn assert_ended(&self) -> &Self {
+        assert_eq!(
+            self.outcome,
+            TransactionOutcome::Ended,
+            "expected transaction {:?} to be ended",
+            self.name
+        );
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics unless the transaction was ignored.
+    pub fn assert_ignored(&self) -> &Self {
+        assert_eq!(
+            self.outcome,
+            TransactionOutcome::Ignored,
+            "expected transaction {:?} to be ignored",
+            self.name
+        );
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics unless an error was noticed for the given HTTP status code.
+    pub fn assert_error(&self, status: u16) -> &Self {
+        assert!(
+            self.has_error_status(status),
+            "expected transaction {:?} to have error {}, but found {:?}",
+            self.name,
+            status,
+            self.errors
+        );
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics unless an error with the given class was noticed.
+    pub fn assert_error_class(&self, class: &str) -> &Self {
+        assert!(
+            self.errors.iter().any(|e| e.class == class),
+            "expected transaction {:?} to have error class {:?}, but found {:?}",
+            self.name,
+            class,
+            self.errors
+        );
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if any errors were noticed.
+    pub fn assert_no_errors(&self) -> &Self {
+        assert!(
+            self.errors.is_empty(),
+            "expected transaction {:?} to have no errors, but found {:?}",
+            self.name,
+            self.errors
+        );
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics unless the attribute was added with the given value.
+    pub fn assert_attribute<V: Into<Attribute>>(&self, key: &str, value: V) -> &Self {
+        let value = value.into();
+        assert_eq!(
+            self.attribute(key),
+            Some(&value),
+            "expected transaction {:?} to have attribute {:?}",
+            self.name,
+            key
+        );
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics unless a custom event of the given type was recorded.
+    pub fn assert_event(&self, event_type: &str) -> &Self {
+        assert!(
+            self.events.iter().any(|e| e.event_type == event_type),
+            "expected transaction {:?} to have event {:?}, but found {:?}",
+            self.name,
+            event_type,
+            self.events
+                .iter()
+                .map(|e| &e.event_type)
+                .collect::<Vec<_>>()
+        );
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics unless a segment with the given name was started.
+    pub fn assert_segment(&self, name: &str) -> &Self {
+        assert!(
+            self.segment(name).is_some(),
+            "expected transaction {:?} to have segment {:?}, but found {:?}",
+            self.name,
+            name,
+            self.segments.iter().map(|s| s.name()).collect::<Vec<_>>()
+        );
+        self
+    }
+}
+
+/// A custom event recorded as part of a transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedEvent {
+    pub event_type: String,
+    pub attributes: Vec<(String, Attribute)>,
+}
+
+/// Whether a recorded transaction was for a web request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransactionKind {
+    Web,
+    Background,
+}
+
+/// How a recorded transaction finished.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransactionOutcome {
+    /// The transaction was ended, and would have been sent to New Relic.
+    Ended,
+    /// The transaction was ignored.
+    Ignored,
+}
+
+/// An error noticed on a recorded transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedError {
+    pub priority: i32,
+    pub message: String,
+    pub class: String,
+}
+
+impl RecordedError {
+    /// The HTTP status code the error was noticed for, if its class is
+    /// a status code such as `HTTP 404`.
+    pub fn status(&self) -> Option<u16> {
+        self.class
+            .strip_prefix("HTTP ")
+            .and_then(|code| code.parse().ok())
+    }
+}
+
+/// A segment recorded as part of a transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedSegment {
+    pub id: SegmentId,
+    pub parent: Option<SegmentId>,
+    pub params: SegmentParams,
+    /// When the segment started, relative to the start of the transaction.
+    pub offset: Duration,
+    /// How long the segment ran for, or `None` if it wasn't ended
+    /// before the transaction finished.
+    pub duration: Option<Duration>,
+}
+
+impl RecordedSegment {
+    /// The name of the segment. Datastore segments are named like
+    /// `Postgres/users/select`, or `Redis/get` without a collection.
+    pub fn name(&self) -> String {
+        match &self.params {
+            SegmentParams::Custom { name, .. } => name.clone(),
+            SegmentParams::Datastore {
+                product,
+                collection,
+                operation,
+                ..
+            } => match collection {
+                Some(collection) => format!("{}/{}/{}", product, collection, operation),
+                None => format!("{}/{}", product, operation),
+            },
+            SegmentParams::External { uri, .. } => uri.clone(),
+        }
+    }
+}
+
+struct RecordingTransaction {
+    backend: RecordingBackend,
+    // Only `None` once the transaction has been recorded.
+    transaction: Option<RecordedTransaction>,
+    started: Instant,
+    open_segments: Vec<(SegmentId, Instant)>,
+}
+
+impl RecordingTransaction {
+    fn transaction(&mut self) -> &mut RecordedTransaction {
+        self.transaction
+            .as_mut()
+            .expect("transaction has already been recorded")
+    }
+
+    fn finish(&mut self, outcome: TransactionOutcome) {
+        if let Some(mut transaction) = self.transaction.take() {
+            transaction.outcome = outcome;
+            transaction.duration = self.started.elapsed();
+            self.backend.record(transaction);
+        }
+    }
+}
+
+impl BackendTransaction for RecordingTransaction {
+    fn end(mut self: Box<Self>) {
+        self.finish(TransactionOutcome::Ended);
+    }
+
+    fn ignore(mut self: Box<Self>) {
+        self.finish(TransactionOutcome::Ignored);
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.transaction().name = name.to_string();
+    }
+
+    fn notice_error(&mut self, priority: i32, message: &str, class: &str) {
+        self.transaction().errors.push(RecordedError {
+            priority,
+            message: message.to_string(),
+            class: class.to_string(),
+        });
+    }
+
+    fn add_attribute(&mut self, key: &str, value: &Attribute) {
+        self.transaction()
+            .attributes
+            .push((key.to_string(), value.clone()));
+    }
+
+    fn record_custom_metric(&mut self, name: &str, value: Duration) {
+        self.transaction().metrics.push((name.to_string(), value));
+    }
+
+    fn record_custom_event(&mut self, event_type: &str, attributes: &[(String, Attribute)]) {
+        self.transaction().events.push(RecordedEvent {
+            event_type: event_type.to_string(),
+            attributes: attributes.to_vec(),
+        });
+    }
+
+    fn start_segment(
+        &mut self,
+        params: &SegmentParams,
+        parent: Option<SegmentId>,
+    ) -> Option<SegmentId> {
+        let now = Instant::now();
+        let offset = now.duration_since(self.started);
+        let segments = &mut self.transaction().segments;
+        let id = SegmentId(segments.len() as u64);
+        segments.push(RecordedSegment {
+            id,
+            parent,
+            params: params.clone(),
+            offset,
+            duration: None,
+        });
+        self.open_segments.push((id, now));
+        Some(id)
+    }
+
+    fn end_segment(&mut self, id: SegmentId) {
+        // Segments can only be ended once.
+        let started = match self.open_segments.iter().find(|(open, _)| *open == id) {
+            Some(&(_, started)) => started,
+            None => return,
+        };
+        let children: Vec<SegmentId> = self
+            .transaction()
+            .segments
+            .iter()
+            .filter(|s| s.parent == Some(id) && s.duration.is_none())
+            .map(|s| s.id)
+            .collect();
+        for child in children {
+            self.end_segment(child);
+        }
+        self.open_segments.retain(|&(open, _)| open != id);
+        self.transaction().segments[id.0 as usize].duration = Some(started.elapsed());
+    }
+
+    fn accept_distributed_trace(&mut self, trace: &InboundTrace) {
+        self.transaction().inbound_trace = Some(trace.clone());
+    }
+
+    fn distributed_trace_payload(&mut self, segment: Option<SegmentId>) -> Option<String> {
+        let name = self.transaction().name.clone();
+        Some(match segment {
+            Some(id) => format!("recorded;transaction={};segment={}", name, id.0),
+            None => format!("recorded;transaction={}", name),
+        })
+    }
+}
+
+impl Drop for RecordingTransaction {
+    // Like the New Relic SDK, transactions which are dropped are ended.
+    fn drop(&mut self) {
+        self.finish(TransactionOutcome::Ended);
+    }
+}
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, time::Duration};
+
+use super::{Backend, BackendTransaction, SegmentId, SegmentParams};
+use crate::{
+    config::{Config, LogLevel},
+    distributed_tracing::InboundTrace,
+    Attribute, Error,
+};
+
+/// A backend which sends transactions to New Relic using the New Relic SDK.
+pub struct NewRelicBackend(newrelic::App);
+
+impl NewRelicBackend {
+    pub fn new(app_name: &str, license_key: &str) -> Result<Self, Error> {
+        Self::from_config(&Config::new(app_name, license_key))
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        if let Some(address) = &config.daemon_address {
+            newrelic::NewRelicConfig::default().socket(address).init()?;
+        }
+        let app_config = newrelic::AppConfig::new(&config.app_name, &config.license_key)?
+            .log_level(sdk_log_level(config.log_level));
+        Ok(NewRelicBackend(newrelic::App::with_config(app_config)?))
+    }
+}
+
+// The SDK's name for a datastore product.
+fn datastore(product: &str) -> newrelic::Datastore {
+    match product.to_ascii_lowercase().as_str() {
+        "firebird" => newrelic::Datastore::Firebird,
+        "informix" => newrelic::Datastore::Informix,
+        "mssql" => newrelic::Datastore::MSSQL,
+        "mysql" => newrelic::Datastore::MySQL,
+        "oracle" => newrelic::Datastore::Oracle,
+        "postgres" | "postgresql" => newrelic::Datastore::Postgres,
+        "sqlite" => newrelic::Datastore::SQLite,
+        "sybase" => newrelic::Datastore::Sybase,
+        "memcache" | "memcached" => newrelic::Datastore::Memcache,
+        "mongodb" => newrelic::Datastore::MongoDB,
+        "odbc" => newrelic::Datastore::ODBC,
+        "redis" => newrelic::Datastore::Redis,
+        _ => newrelic::Datastore::Other(product.to_string()),
+    }
+}
+
+fn sdk_log_level(level: LogLevel) -> newrelic::LogLevel {
+    match level {
+        LogLevel::Error => newrelic::LogLevel::Error,
+        LogLevel::Warning => newrelic::LogLevel::Warning,
+        LogLevel::Info => newrelic::LogLevel::Info,
+        LogLevel::Debug => newrelic::LogLevel::Debug,
+    }
+}
+
+// The SDK has no boolean attributes, so booleans are sent as strings.
+fn sdk_attribute(value: &Attribute) -> newrelic::Attribute {
+    match value {
+        Attribute::String(s) => s.as_str().into(),
+        Attribute::Int(i) => (*i).into(),
+        Attribute::Float(f) => (*f).into(),
+        Attribute::Bool(b) => if *b { "true" } else { "false" }.into(),
+    }
+}
+
+impl Backend for NewRelicBackend {
+    fn start_web_transaction(&self, name: &str) -> Option<Box<dyn BackendTransaction>> {
+        self.0
+            .web_transaction(name)
+            .ok()
+            .map(|t| Box::new(SdkTransaction::new(t)) as Box<dyn BackendTransaction>)
+    }
+
+    fn start_background_transaction(&self, name: &str) -> Option<Box<dyn BackendTransaction>> {
+        self.0
+            .non_web_transaction(name)
+            .ok()
+            .map(|t| Box::new(SdkTransaction::new(t)) as Box<dyn BackendTransaction>)
+    }
+}
+
+// A New Relic transaction along with its open segments.
+//
+// SDK segments borrow the transaction they belong to, so they can't be
+// stored next to it safely. The transaction is boxed so that it never
+// moves, and segments are always ended before the transaction is, so
+// extending their lifetimes to 'static is sound as long as they never
+// escape this struct.
+struct SdkTransaction {
+    // Declared before the transaction so that they are dropped first.
+    segments: HashMap<SegmentId, OpenSegment>,
+    next_segment: u64,
+    transaction: Box<newrelic::Transaction>,
+}
+
+struct OpenSegment {
+    parent: Option<SegmentId>,
+    segment: newrelic::Segment<'static>,
+}
+
+// The SDK's transactions and segments are raw pointers into the C SDK,
+// which allows them to be used from any thread, one thread at a time.
+unsafe impl Send for SdkTransaction {}
+
+impl SdkTransaction {
+    fn new(transaction: newrelic::Transaction) -> Self {
+        SdkTransaction {
+            segments: HashMap::new(),
+            next_segment: 0,
+            transaction: Box::new(transaction),
+        }
+    }
+
+    // End all open segments.
+    fn end_segments(&mut self) {
+        while let Some(&id) = self.segments.keys().next() {
+            self.end_segment(id);
+        }
+    }
+}
+
+impl BackendTransaction for SdkTransaction {
+    fn end(mut self: Box<Self>) {
+        self.end_segments();
+        self.transaction.end();
+    }
+
+    fn ignore(mut self: Box<Self>) {
+        self.end_segments();
+        self.transaction.ignore();
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.transaction.set_name(name).ok();
+    }
+
+    fn notice_error(&mut self, priority: i32, message: &str, class: &str) {
+        self.transaction.notice_error(priority, message, class).ok();
+    }
+
+    fn add_attribute(&mut self, key: &str, value: &Attribute) {
+        self.transaction
+            .add_attribute(key, sdk_attribute(value))
+            .ok();
+    }
+
+    fn record_custom_metric(&mut self, name: &str, value: Duration) {
+        let millis = value.as_secs() as f64 * 1e3 + f64::from(value.subsec_nanos()) / 1e6;
+        self.transaction.record_custom_metric(name, millis).ok();
+    }
+
+    fn record_custom_event(&mut self, event_type: &str, attributes: &[(String, Attribute)]) {
+        let mut event = match newrelic::CustomEvent::new(event_type) {
+            Ok(event) => event,
+            Err(_) => return,
+        };
+        for (key, value) in attributes {
+            event.add_attribute(key, sdk_attribute(value)).ok();
+        }
+        self.transaction.record_custom_event(event);
+    }
+
+    fn start_segment(
+        &mut self,
+        params: &SegmentParams,
+        parent: Option<SegmentId>,
+    ) -> Option<SegmentId> {
+        let parent_segment = parent
+            .and_then(|id| self.segments.get(&id))
+            .map(|open| &open.segment);
+        let segment = match params {
+            SegmentParams::Custom { name, category } => match parent_segment {
+                Some(parent) => parent.custom_nested(name, category, |s| s),
+                None => self.transaction.custom_segment(name, category, |s| s),
+            },
+            SegmentParams::Datastore {
+                product,
+                collection,
+                operation,
+                query,
+            } => {
+                let mut builder =
+                    newrelic::DatastoreParamsBuilder::new(datastore(product)).operation(operation);
+                if let Some(collection) = collection {
+                    builder = builder.collection(collection);
+                }
+                if let Some(query) = query {
+                    builder = builder.query(query);
+                }
+                let params = builder.build().ok()?;
+                match parent_segment {
+                    Some(parent) => parent.datastore_nested(&params, |s| s),
+                    None => self.transaction.datastore_segment(&params, |s| s),
+                }
+            }
+            SegmentParams::External {
+                uri,
+                procedure,
+                library,
+            } => {
+                let mut builder = newrelic::ExternalParamsBuilder::new(uri);
+                if let Some(procedure) = procedure {
+                    builder = builder.procedure(procedure);
+                }
+                if let Some(library) = library {
+                    builder = builder.library(library);
+                }
+                let params = builder.build().ok()?;
+                match parent_segment {
+                    Some(parent) => parent.external_nested(&params, |s| s),
+                    None => self.transaction.external_segment(&params, |s| s),
+                }
+            }
+        };
+        // See the comment on `SdkTransaction` for why this is sound.
+        let segment: newrelic::Segment<'static> = unsafe { std::mem::transmute(segment) };
+
+        let id = SegmentId(self.next_segment);
+        self.next_segment += 1;
+        self.segments.insert(id, OpenSegment { parent, segment });
+        Some(id)
+    }
+
+    fn end_segment(&mut self, id: SegmentId) {
+        // Nested segments borrow their parent, so must be ended first.
+        let children: Vec<SegmentId> = self
+            .segments
+            .iter()
+            .filter(|(_, open)| open.parent == Some(id))
+            .map(|(&child, _)| child)
+            .collect();
+        for child in children {
+            self.end_segment(child);
+        }
+        // SDK segments end when they're dropped.
+        self.segments.remove(&id);
+    }
+
+    fn accept_distributed_trace(&mut self, trace: &InboundTrace) {
+        // The SDK only understands New Relic's own header.
+        if let Some(payload) = &trace.newrelic {
+            self.transaction
+                .accept_distributed_trace_payload(payload, newrelic::TransportType::HTTP)
+                .ok();
+        }
+    }
+
+    fn distributed_trace_payload(&mut self, segment: Option<SegmentId>) -> Option<String> {
+        match segment.and_then(|id| self.segments.get(&id)) {
+            Some(open) => open.segment.distributed_trace(),
+            None => self.transaction.distributed_trace(),
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::{env, str::FromStr};
+
+use rocket::config::{self as rocket_config, Table, Value};
+
+use crate::Error;
+
+// The name of the table in Rocket's config holding New Relic settings.
+const TABLE: &str = "newrelic";
+
+/// Settings for the New Relic fairing.
+///
+/// These are usually read from Rocket's config by `NewRelic::fairing`,
+/// from a `newrelic` table such as:
+///
+/// ```toml
+/// [global.newrelic]
+/// app_name = "my-service"
+/// license_key = "0123456789012345678901234567890123456789"
+/// daemon_address = "/tmp/.newrelic.sock"
+/// enabled = true
+/// log_level = "info"
+/// name_with_method = true
+/// ```
+///
+/// Settings can be given for a single environment instead, e.g. under
+/// `[production.newrelic]`, but not in both places: Rocket replaces the
+/// whole environment table with the global one, rather than merging
+/// them key by key. Use environment variables to vary individual
+/// settings; they take precedence over Rocket's config, and their names
+/// are listed under `Config::from_rocket`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub app_name: String,
+    pub license_key: String,
+    /// The address of the New Relic daemon: a socket path, or a
+    /// `host:port` pair. The SDK's default is used if this is `None`.
+    pub daemon_address: Option<String>,
+    /// If false, the fairing is attached but records nothing.
+    pub enabled: bool,
+    /// The log level of the New Relic SDK.
+    pub log_level: LogLevel,
+    /// Whether the default transaction names include the request method,
+    /// e.g. `GET api/get_user` rather than `api/get_user`.
+    pub name_with_method: bool,
+}
+
+impl Config {
+    pub fn new(app_name: &str, license_key: &str) -> Self {
+        Config {
+            app_name: app_name.to_string(),
+            license_key: license_key.to_string(),
+            daemon_address: None,
+            enabled: true,
+            log_level: LogLevel::default(),
+            name_with_method: true,
+        }
+    }
+
+    /// Read the New Relic settings from Rocket's config.
+    ///
+    /// Each setting can be overridden by an environment variable:
+    /// `NEW_RELIC_APP_NAME`, `NEW_RELIC_LICENSE_KEY`,
+    /// `NEW_RELIC_DAEMON_ADDRESS`, `NEW_RELIC_ENABLED`,
+    /// `NEW_RELIC_LOG_LEVEL` and `NEW_RELIC_NAME_WITH_METHOD`.
+    pub fn from_rocket(config: &rocket_config::Config) -> Result<Self, Error> {
+        let empty = Table::new();
+        let table = match config.get_table(TABLE) {
+            Ok(table) => table,
+            Err(rocket_config::ConfigError::Missing(_)) => &empty,
+            Err(_) => return Err(Error::invalid_config("newrelic", "must be a table")),
+        };
+
+        let enabled = match setting(table, "enabled", "NEW_RELIC_ENABLED")? {
+            Some(enabled) => parse("enabled", &enabled)?,
+            None => true,
+        };
+        let log_level = match setting(table, "log_level", "NEW_RELIC_LOG_LEVEL")? {
+            Some(level) => parse("log_level", &level)?,
+            None => LogLevel::default(),
+        };
+        let name_with_method =
+            match setting(table, "name_with_method", "NEW_RELIC_NAME_WITH_METHOD")? {
+                Some(name_with_method) => parse("name_with_method", &name_with_method)?,
+                None => true,
+            };
+        let daemon_address = setting(table, "daemon_address", "NEW_RELIC_DAEMON_ADDRESS")?;
+        let app_name = setting(table, "app_name", "NEW_RELIC_APP_NAME")?;
+        let license_key = setting(table, "license_key", "NEW_RELIC_LICENSE_KEY")?;
+
+        // The app name and license key aren't needed if the fairing is disabled.
+        let (app_name, license_key) = if enabled {
+            (
+                app_name.ok_or(Error::MissingConfig("app_name"))?,
+                license_key.ok_or(Error::MissingConfig("license_key"))?,
+            )
+        } else {
+            (
+                app_name.unwrap_or_default(),
+                license_key.unwrap_or_default(),
+            )
+        };
+
+        let config = Config {
+            app_name,
+            license_key,
+            daemon_address,
+            enabled,
+            log_level,
+            name_with_method,
+        };
+        if config.enabled {
+            config.validate()?;
+        }
+        Ok(config)
+    }
+
+    /// Check that the settings are valid.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.app_name.trim().is_empty() {
+            return Err(Error::invalid_config("app_name", "must not be empty"));
+        }
+        // New Relic license keys are 40 characters long.
+        if self.license_key.len() != 40
+            || !self.license_key.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(Error::invalid_config(
+                "license_key",
+                "must be 40 alphanumeric characters",
+            ));
+        }
+        if let Some(address) = &self.daemon_address {
+            if address.trim().is_empty() {
+                return Err(Error::invalid_config("daemon_address", "must not be empty"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The log level of the New Relic SDK.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogLevel {
+    Error,
+    Warning,
+    #[default]
+    Info,
+    Debug,
+}
+
+impl FromStr for LogLevel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warning),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(()),
+        }
+    }
+}
+
+// Look up a setting, preferring the environment variable over Rocket's config.
+fn setting(table: &Table, key: &'static str, var: &str) -> Result<Option<String>, Error> {
+    if let Ok(value) = env::var(var) {
+        return Ok(Some(value));
+    }
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(Value::Boolean(b)) => Ok(Some(b.to_string())),
+        Some(_) => Err(Error::invalid_config(key, "must be a string")),
+    }
+}
+
+fn parse<T: FromStr>(key: &'static str, value: &str) -> Result<T, Error> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| Error::invalid_config(key, &format!("invalid value {:?}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard, PoisonError};
+
+    use rocket::config::Environment;
+
+    use super::*;
+
+    const LICENSE_KEY: &str = "0123456789012345678901234567890123456789";
+
+    const VARS: &[&str] = &[
+        "NEW_RELIC_APP_NAME",
+        "NEW_RELIC_LICENSE_KEY",
+        "NEW_RELIC_DAEMON_ADDRESS",
+        "NEW_RELIC_ENABLED",
+        "NEW_RELIC_LOG_LEVEL",
+        "NEW_RELIC_NAME_WITH_METHOD",
+    ];
+
+    // Environment variables are shared by every test, so tests reading
+    // the config take turns, starting with none of the variables set.
+    fn lock_env() -> MutexGuard<'static, ()> {
+        static ENV: Mutex<()> = Mutex::new(());
+        let guard = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+        for var in VARS {
+            env::remove_var(var);
+        }
+        guard
+    }
+
+    fn rocket_config(settings: &[(&str, Value)]) -> rocket_config::Config {
+        let table: Table = settings
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect();
+        rocket_config::Config::build(Environment::Development)
+            .extra(TABLE, table)
+            .finalize()
+            .unwrap()
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn reads_settings_from_table() {
+        let _env = lock_env();
+        let config = rocket_config(&[
+            ("app_name", string("my-service")),
+            ("license_key", string(LICENSE_KEY)),
+            ("daemon_address", string("/tmp/.newrelic.sock")),
+            ("log_level", string("debug")),
+            ("name_with_method", Value::Boolean(false)),
+        ]);
+
+        let config = Config::from_rocket(&config).unwrap();
+        assert_eq!(config.app_name, "my-service");
+        assert_eq!(config.license_key, LICENSE_KEY);
+        assert_eq!(
+            config.daemon_address.as_deref(),
+            Some("/tmp/.newrelic.sock")
+        );
+        assert!(config.enabled);
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert!(!config.name_with_method);
+    }
+
+    #[test]
+    fn uses_defaults_for_optional_settings() {
+        let _env = lock_env();
+        let config = rocket_config(&[
+            ("app_name", string("my-service")),
+            ("license_key", string(LICENSE_KEY)),
+        ]);
+
+        assert_eq!(
+            Config::from_rocket(&config).unwrap(),
+            Config::new("my-service", LICENSE_KEY)
+        );
+    }
+
+    #[test]
+    fn requires_app_name_and_license_key() {
+        let _env = lock_env();
+        let missing_table = rocket_config::Config::development();
+        match Config::from_rocket(&missing_table) {
+            Err(Error::MissingConfig("app_name")) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let config = rocket_config(&[("app_name", string("my-service"))]);
+        match Config::from_rocket(&config) {
+            Err(Error::MissingConfig("license_key")) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn disabled_config_needs_no_other_settings() {
+        let _env = lock_env();
+        let config = rocket_config(&[("enabled", Value::Boolean(false))]);
+
+        let config = Config::from_rocket(&config).unwrap();
+        assert!(!config.enabled);
+        assert_eq!(config.license_key, "");
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let _env = lock_env();
+        let invalid = [
+            ("license_key", string("too-short")),
+            ("app_name", string("  ")),
+            ("log_level", string("verbose")),
+            ("enabled", string("maybe")),
+            ("daemon_address", Value::Integer(31339)),
+        ];
+        for (key, value) in &invalid {
+            let mut settings = vec![
+                ("app_name", string("my-service")),
+                ("license_key", string(LICENSE_KEY)),
+            ];
+            settings.retain(|(k, _)| k != key);
+            settings.push((*key, value.clone()));
+            match Config::from_rocket(&rocket_config(&settings)) {
+                Err(Error::InvalidConfig { key: k, .. }) if k == *key => {}
+                other => panic!("unexpected result for {}: {:?}", key, other),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_non_table() {
+        let _env = lock_env();
+        let config = rocket_config::Config::build(Environment::Development)
+            .extra(TABLE, "my-service")
+            .finalize()
+            .unwrap();
+        match Config::from_rocket(&config) {
+            Err(Error::InvalidConfig {
+                key: "newrelic", ..
+            }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn environment_variables_override_table() {
+        let _env = lock_env();
+        let config = rocket_config(&[
+            ("app_name", string("my-service")),
+            ("license_key", string("not-a-valid-key")),
+            ("log_level", string("debug")),
+        ]);
+        env::set_var("NEW_RELIC_APP_NAME", "from-env");
+        env::set_var("NEW_RELIC_LICENSE_KEY", LICENSE_KEY);
+        env::set_var("NEW_RELIC_LOG_LEVEL", "Error");
+        env::set_var("NEW_RELIC_NAME_WITH_METHOD", "false");
+
+        let config = Config::from_rocket(&config).unwrap();
+        assert_eq!(config.app_name, "from-env");
+        assert_eq!(config.license_key, LICENSE_KEY);
+        assert_eq!(config.log_level, LogLevel::Error);
+        assert!(!config.name_with_method);
+    }
+
+    #[test]
+    fn environment_variables_can_disable() {
+        let _env = lock_env();
+        env::set_var("NEW_RELIC_ENABLED", "false");
+
+        let config = Config::from_rocket(&rocket_config::Config::development()).unwrap();
+        assert!(!config.enabled);
+    }
+}
@@ -0,0 +1,55 @@
+//! Datastore segments for common database clients.
+//!
+//! These are enabled by the `diesel` and `redis` features.
+
+use crate::Transaction;
+
+#[cfg(feature = "diesel")]
+impl Transaction {
+    /// Run a Diesel query against Postgres in a datastore segment.
+    ///
+    /// `query` is only used for its SQL; `run` should execute it.
+    ///
+    /// ```ignore
+    /// let query = users::table.filter(users::id.eq(id));
+    /// let user = txn.postgres("users", "select", &query, || query.first::<User>(&conn))?;
+    /// ```
+    pub fn postgres<Q, F, T>(&self, collection: &str, operation: &str, query: &Q, run: F) -> T
+    where
+        Q: diesel::query_builder::QueryFragment<diesel::pg::Pg>,
+        F: FnOnce() -> T,
+    {
+        let sql = diesel::debug_query::<diesel::pg::Pg, _>(query).to_string();
+        // Leave out the bound parameters, which may be sensitive.
+        let sql = sql.split(" -- binds:").next().unwrap_or_default();
+        let _segment = self.datastore_segment("Postgres", Some(collection), operation, Some(sql));
+        run()
+    }
+}
+
+#[cfg(feature = "redis")]
+impl Transaction {
+    /// Run a Redis command in a datastore segment.
+    ///
+    /// The segment's operation is the command name, e.g. `get`.
+    pub fn redis<T: redis::FromRedisValue>(
+        &self,
+        cmd: &redis::Cmd,
+        con: &mut dyn redis::ConnectionLike,
+    ) -> redis::RedisResult<T> {
+        let _segment = self.datastore_segment("Redis", None, &redis_operation(cmd), None);
+        cmd.query(con)
+    }
+}
+
+// The name of a Redis command, e.g. `get`.
+#[cfg(feature = "redis")]
+fn redis_operation(cmd: &redis::Cmd) -> String {
+    // Commands are packed as `*<args>\r\n$<length>\r\n<name>\r\n...`.
+    let packed = cmd.get_packed_command();
+    String::from_utf8_lossy(&packed)
+        .split("\r\n")
+        .nth(2)
+        .unwrap_or("unknown")
+        .to_ascii_lowercase()
+}
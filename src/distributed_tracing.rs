@@ -0,0 +1,241 @@
+//! Continuing distributed traces from upstream services.
+
+use std::{collections::HashSet, net::IpAddr};
+
+use rocket::Request;
+
+/// Distributed tracing headers sent with an incoming request.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InboundTrace {
+    /// New Relic's own `newrelic` header.
+    pub newrelic: Option<String>,
+    /// The W3C `traceparent` header.
+    pub traceparent: Option<String>,
+    /// The W3C `tracestate` header.
+    pub tracestate: Option<String>,
+}
+
+impl InboundTrace {
+    // Read the tracing headers from a request, if it has any.
+    pub(crate) fn from_request(request: &Request) -> Option<Self> {
+        let headers = request.headers();
+        // A request with more than one `traceparent` is invalid, but
+        // multiple `tracestate` headers are combined into one list.
+        let traceparent = match headers.get("traceparent").collect::<Vec<_>>().as_slice() {
+            [traceparent] => Some(traceparent.to_string()),
+            _ => None,
+        };
+        let tracestate: Vec<&str> = headers.get("tracestate").collect();
+        let trace = InboundTrace {
+            newrelic: headers.get_one("newrelic").map(str::to_string),
+            traceparent,
+            tracestate: if tracestate.is_empty() {
+                None
+            } else {
+                Some(tracestate.join(","))
+            },
+        };
+        if trace == InboundTrace::default() {
+            None
+        } else {
+            Some(trace)
+        }
+    }
+}
+
+/// Decides which requests' distributed tracing headers are accepted.
+///
+/// By default, headers are accepted from any client.
+pub struct TracePolicy {
+    enabled: bool,
+    // `None` if any client is trusted.
+    trusted: Option<HashSet<IpAddr>>,
+    trust_real_ip: bool,
+}
+
+impl Default for TracePolicy {
+    fn default() -> Self {
+        TracePolicy {
+            enabled: true,
+            trusted: None,
+            trust_real_ip: false,
+        }
+    }
+}
+
+impl TracePolicy {
+    /// Never accept distributed tracing headers, so every transaction
+    /// starts a new trace.
+    pub fn disabled() -> Self {
+        TracePolicy {
+            enabled: false,
+            trusted: None,
+            trust_real_ip: false,
+        }
+    }
+
+    /// Only accept distributed tracing headers from clients with the
+    /// given IP addresses.
+    pub fn trusted<I: IntoIterator<Item = IpAddr>>(addresses: I) -> Self {
+        TracePolicy {
+            enabled: true,
+            trusted: Some(addresses.into_iter().collect()),
+            trust_real_ip: false,
+        }
+    }
+
+    /// Check trusted addresses against the `X-Real-IP` header, rather than
+    /// the address of the connecting client.
+    ///
+    /// Any client can send `X-Real-IP`, so only use this behind a proxy
+    /// which always sets it.
+    pub fn trust_real_ip(mut self) -> Self {
+        self.trust_real_ip = true;
+        self
+    }
+
+    /// Whether to accept the distributed tracing headers of a request.
+    pub fn accepts(&self, request: &Request) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        match &self.trusted {
+            None => true,
+            Some(trusted) => {
+                let ip = if self.trust_real_ip {
+                    request.real_ip()
+                } else {
+                    request.remote().map(|address| address.ip())
+                };
+                ip.map(|ip| trusted.contains(&ip)).unwrap_or(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use rocket::{http::Header, local::Client};
+
+    use super::*;
+
+    const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    fn localhost() -> IpAddr {
+        Ipv4Addr::LOCALHOST.into()
+    }
+
+    fn client() -> Client {
+        Client::new(rocket::ignite()).unwrap()
+    }
+
+    #[test]
+    fn reads_tracing_headers() {
+        let client = client();
+        let request = client
+            .get("/")
+            .header(Header::new("newrelic", "payload"))
+            .header(Header::new("traceparent", TRACEPARENT))
+            .header(Header::new("tracestate", "a=1"));
+        assert_eq!(
+            InboundTrace::from_request(request.inner()),
+            Some(InboundTrace {
+                newrelic: Some("payload".to_string()),
+                traceparent: Some(TRACEPARENT.to_string()),
+                tracestate: Some("a=1".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_requests_without_tracing_headers() {
+        let client = client();
+        let request = client.get("/");
+        assert_eq!(InboundTrace::from_request(request.inner()), None);
+    }
+
+    #[test]
+    fn joins_multiple_tracestate_headers() {
+        let client = client();
+        let request = client
+            .get("/")
+            .header(Header::new("tracestate", "a=1,b=2"))
+            .header(Header::new("tracestate", "c=3"));
+        let trace = InboundTrace::from_request(request.inner()).unwrap();
+        assert_eq!(trace.tracestate.as_deref(), Some("a=1,b=2,c=3"));
+    }
+
+    #[test]
+    fn drops_duplicate_traceparent_headers() {
+        let client = client();
+        let request = client
+            .get("/")
+            .header(Header::new("traceparent", TRACEPARENT))
+            .header(Header::new("traceparent", TRACEPARENT))
+            .header(Header::new("tracestate", "a=1"));
+        let trace = InboundTrace::from_request(request.inner()).unwrap();
+        assert_eq!(trace.traceparent, None);
+        assert_eq!(trace.tracestate.as_deref(), Some("a=1"));
+    }
+
+    #[test]
+    fn default_accepts_any_client() {
+        let client = client();
+        assert!(TracePolicy::default().accepts(client.get("/").inner()));
+        let request = client.get("/").remote(SocketAddr::new(localhost(), 8000));
+        assert!(TracePolicy::default().accepts(request.inner()));
+    }
+
+    #[test]
+    fn disabled_accepts_no_client() {
+        let client = client();
+        let request = client.get("/").remote(SocketAddr::new(localhost(), 8000));
+        assert!(!TracePolicy::disabled().accepts(request.inner()));
+    }
+
+    #[test]
+    fn trusted_accepts_only_trusted_addresses() {
+        let policy = TracePolicy::trusted(vec![localhost()]);
+        let client = client();
+
+        let trusted = client.get("/").remote(SocketAddr::new(localhost(), 8000));
+        assert!(policy.accepts(trusted.inner()));
+
+        let untrusted = client
+            .get("/")
+            .remote(SocketAddr::new(Ipv4Addr::new(10, 0, 0, 1).into(), 8000));
+        assert!(!policy.accepts(untrusted.inner()));
+
+        // Without an address, the client can't be trusted.
+        assert!(!policy.accepts(client.get("/").inner()));
+    }
+
+    #[test]
+    fn trusted_ignores_real_ip_by_default() {
+        let policy = TracePolicy::trusted(vec![localhost()]);
+        let client = client();
+        let request = client
+            .get("/")
+            .remote(SocketAddr::new(Ipv4Addr::new(10, 0, 0, 1).into(), 8000))
+            .header(Header::new("X-Real-IP", "127.0.0.1"));
+        assert!(!policy.accepts(request.inner()));
+    }
+
+    #[test]
+    fn trust_real_ip_checks_the_real_ip_header() {
+        let policy = TracePolicy::trusted(vec![localhost()]).trust_real_ip();
+        let client = client();
+
+        let trusted = client
+            .get("/")
+            .remote(SocketAddr::new(Ipv4Addr::new(10, 0, 0, 1).into(), 8000))
+            .header(Header::new("X-Real-IP", "127.0.0.1"));
+        assert!(policy.accepts(trusted.inner()));
+
+        // The connecting address no longer counts.
+        let untrusted = client.get("/").remote(SocketAddr::new(localhost(), 8000));
+        assert!(!policy.accepts(untrusted.inner()));
+    }
+}
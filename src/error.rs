@@ -0,0 +1,55 @@
+use std::{error, fmt};
+
+/// An error setting up New Relic instrumentation.
+#[derive(Debug)]
+pub enum Error {
+    /// The New Relic SDK couldn't create the app, usually because the
+    /// license key was invalid or the daemon couldn't be reached.
+    App(newrelic::Error),
+
+    /// A required setting was missing from the New Relic config.
+    MissingConfig(&'static str),
+
+    /// A setting in the New Relic config was invalid.
+    InvalidConfig { key: &'static str, reason: String },
+}
+
+impl Error {
+    pub(crate) fn invalid_config(key: &'static str, reason: &str) -> Self {
+        Error::InvalidConfig {
+            key,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::App(e) => write!(f, "could not create New Relic app: {}", e),
+            Error::MissingConfig(key) => write!(f, "missing New Relic setting `newrelic.{}`", key),
+            Error::InvalidConfig { key, reason } => {
+                write!(
+                    f,
+                    "invalid New Relic setting `newrelic.{}`: {}",
+                    key, reason
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::App(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<newrelic::Error> for Error {
+    fn from(e: newrelic::Error) -> Self {
+        Error::App(e)
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    any, error,
+    io::{Cursor, Read},
+    sync::{Mutex, PoisonError},
+};
+
+use rocket::{
+    response::{self, Body, Responder},
+    Request, Response,
+};
+
+// The largest response body used as an error message.
+const MAX_MESSAGE_LENGTH: u64 = 1024;
+
+/// The class and message of an error reported to New Relic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorDetail {
+    pub class: String,
+    pub message: String,
+}
+
+impl ErrorDetail {
+    pub fn new(class: &str, message: &str) -> Self {
+        ErrorDetail {
+            class: class.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Use the error's type name as the class, and its `Display` output
+    /// followed by those of its sources as the message,
+    /// e.g. `could not load user: connection refused`.
+    ///
+    /// The type name is only known at compile time, so trait objects such
+    /// as `Box<dyn Error>` all get the same class, e.g.
+    /// `dyn core::error::Error`, and end up in one error group. Give these
+    /// a class of their own instead:
+    ///
+    /// ```
+    /// use newrelic_fairing::ErrorDetail;
+    ///
+    /// let error: Box<dyn std::error::Error> = "connection refused".into();
+    /// let detail = ErrorDetail {
+    ///     class: "UserLookupError".to_string(),
+    ///     ..ErrorDetail::from_error(&*error)
+    /// };
+    /// ```
+    pub fn from_error<E: error::Error + ?Sized>(error: &E) -> Self {
+        let mut message = error.to_string();
+        let mut source = error.source();
+        while let Some(e) = source {
+            message.push_str(": ");
+            message.push_str(&e.to_string());
+            source = e.source();
+        }
+        ErrorDetail::new(any::type_name::<E>(), &message)
+    }
+
+    /// Report this error if the response for the request is an error,
+    /// rather than details taken from the response itself.
+    pub fn attach(self, request: &Request) {
+        *slot(request).lock().unwrap_or_else(PoisonError::into_inner) = Some(self);
+    }
+
+    // Take the details attached to the request, if any.
+    pub(crate) fn take(request: &Request) -> Option<Self> {
+        slot(request)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    // Details for an error response, when the handler didn't attach any.
+    //
+    // The class is the status code, e.g. `HTTP 503`. The message is the
+    // response body if it's short and textual, or the status otherwise.
+    pub(crate) fn from_response(response: &mut Response) -> Self {
+        let status = response.status();
+        let message = body_message(response).unwrap_or_else(|| status.to_string());
+        ErrorDetail::new(&format!("HTTP {}", status.code), &message)
+    }
+}
+
+// Error details attached to a request.
+struct Slot(Mutex<Option<ErrorDetail>>);
+
+fn slot<'r>(request: &'r Request) -> &'r Slot {
+    request.local_cache(|| Slot(Mutex::new(None)))
+}
+
+// Read a short, textual response body, putting it back afterwards.
+fn body_message(response: &mut Response) -> Option<String> {
+    let is_text = response
+        .content_type()
+        .map(|ct| ct.top() == "text" || ct.is_json())
+        .unwrap_or(false);
+    if !is_text {
+        return None;
+    }
+
+    let mut bytes = Vec::new();
+    let read = match response.body() {
+        Some(Body::Sized(body, size)) if size <= MAX_MESSAGE_LENGTH => body.read_to_end(&mut bytes),
+        _ => return None,
+    };
+    // The body has been consumed, so must be replaced even if reading it failed.
+    response.set_sized_body(Cursor::new(bytes.clone()));
+    read.ok()?;
+
+    let message = String::from_utf8(bytes).ok()?;
+    let message = message.trim();
+    if message.is_empty() {
+        None
+    } else {
+        Some(message.to_string())
+    }
+}
+
+/// A responder which reports its error to New Relic using
+/// `ErrorDetail::from_error`, if the response is an error.
+///
+/// Handlers can return e.g. `Result<Json<User>, Reported<DatabaseError>>`.
+pub struct Reported<E>(pub E);
+
+impl<'r, E> Responder<'r> for Reported<E>
+where
+    E: error::Error + Responder<'r>,
+{
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        ErrorDetail::from_error(&self.0).attach(request);
+        self.0.respond_to(request)
+    }
+}
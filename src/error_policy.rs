@@ -0,0 +1,228 @@
+//! Deciding which responses are reported to New Relic as errors.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+};
+
+use rocket::{Request, Response};
+
+// The priority of errors whose status isn't in any configured range.
+pub(crate) const DEFAULT_PRIORITY: i32 = 50;
+
+type Decider = dyn Fn(&Request, &Response) -> Option<bool> + Send + Sync;
+
+/// Decides whether a response should be reported as an error.
+///
+/// By default, responses with a 4xx or 5xx status are reported, except
+/// for 404s. Decisions are made in this order:
+///
+/// 1. the custom callback, if any, and if it returns `Some`;
+/// 2. the policy for the route that handled the request, if any;
+/// 3. the ignored status codes;
+/// 4. the reported status ranges.
+///
+/// Errors are reported with a priority depending on their status: 100 for
+/// 5xx responses and 50 for anything else, by default.
+pub struct ErrorPolicy {
+    ranges: Vec<RangeInclusive<u16>>,
+    priorities: Vec<(RangeInclusive<u16>, i32)>,
+    ignored: HashSet<u16>,
+    routes: HashMap<String, ErrorPolicy>,
+    custom: Option<Box<Decider>>,
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> Self {
+        ErrorPolicy::empty()
+            .report(400..=599)
+            .ignore(404)
+            .priority(500..=599, 100)
+    }
+}
+
+impl ErrorPolicy {
+    /// A policy which reports no errors.
+    pub fn empty() -> Self {
+        ErrorPolicy {
+            ranges: Vec::new(),
+            priorities: Vec::new(),
+            ignored: HashSet::new(),
+            routes: HashMap::new(),
+            custom: None,
+        }
+    }
+
+    /// Report responses with a status in the given range.
+    pub fn report(mut self, statuses: RangeInclusive<u16>) -> Self {
+        self.ranges.push(statuses);
+        self
+    }
+
+    /// Never report responses with the given status.
+    pub fn ignore(mut self, status: u16) -> Self {
+        self.ignored.insert(status);
+        self
+    }
+
+    /// Report errors with a status in the given range using the given
+    /// priority.
+    ///
+    /// If ranges overlap, the range given last takes precedence.
+    pub fn priority(mut self, statuses: RangeInclusive<u16>, priority: i32) -> Self {
+        self.priorities.push((statuses, priority));
+        self
+    }
+
+    /// Use a different policy for requests handled by the named route.
+    ///
+    /// Routes are named after their handler functions.
+    pub fn route(mut self, name: &str, policy: ErrorPolicy) -> Self {
+        self.routes.insert(name.to_string(), policy);
+        self
+    }
+
+    /// Decide using a callback, which returns `None` to fall back to the
+    /// rest of the policy.
+    pub fn custom<F>(mut self, decide: F) -> Self
+    where
+        F: Fn(&Request, &Response) -> Option<bool> + Send + Sync + 'static,
+    {
+        self.custom = Some(Box::new(decide));
+        self
+    }
+
+    /// Whether the response should be reported as an error.
+    pub fn is_error(&self, request: &Request, response: &Response) -> bool {
+        if let Some(decision) = self.custom.as_ref().and_then(|f| f(request, response)) {
+            return decision;
+        }
+        if let Some(policy) = self.route_policy(request) {
+            return policy.is_error(request, response);
+        }
+        let status = response.status().code;
+        !self.ignored.contains(&status) && self.ranges.iter().any(|r| r.contains(&status))
+    }
+
+    /// The priority to report an error with.
+    pub fn priority_for(&self, request: &Request, status: u16) -> i32 {
+        if let Some(policy) = self.route_policy(request) {
+            return policy.priority_for(request, status);
+        }
+        self.priorities
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&status))
+            .map(|&(_, priority)| priority)
+            .unwrap_or(DEFAULT_PRIORITY)
+    }
+
+    // The policy for the route that handled the request, if it has one.
+    fn route_policy(&self, request: &Request) -> Option<&ErrorPolicy> {
+        request
+            .route()
+            .and_then(|route| route.name)
+            .and_then(|name| self.routes.get(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{http::Status, local::Client};
+
+    use super::*;
+
+    fn response(code: u16) -> Response<'static> {
+        Response::build()
+            .status(Status::from_code(code).unwrap_or_else(|| Status::new(code, "Custom")))
+            .finalize()
+    }
+
+    // Whether each status is reported by the policy, for a request no
+    // route has handled.
+    fn reported(policy: &ErrorPolicy, codes: &[u16]) -> Vec<bool> {
+        let client = Client::new(rocket::ignite()).unwrap();
+        let request = client.get("/");
+        codes
+            .iter()
+            .map(|&code| policy.is_error(request.inner(), &response(code)))
+            .collect()
+    }
+
+    fn priorities(policy: &ErrorPolicy, codes: &[u16]) -> Vec<i32> {
+        let client = Client::new(rocket::ignite()).unwrap();
+        let request = client.get("/");
+        codes
+            .iter()
+            .map(|&code| policy.priority_for(request.inner(), code))
+            .collect()
+    }
+
+    #[test]
+    fn default_reports_errors_except_not_found() {
+        assert_eq!(
+            reported(
+                &ErrorPolicy::default(),
+                &[200, 302, 304, 400, 404, 499, 500, 599]
+            ),
+            [false, false, false, true, false, true, true, true]
+        );
+    }
+
+    #[test]
+    fn empty_reports_nothing() {
+        assert_eq!(
+            reported(&ErrorPolicy::empty(), &[400, 404, 500]),
+            [false, false, false]
+        );
+    }
+
+    #[test]
+    fn reports_configured_ranges() {
+        let policy = ErrorPolicy::empty().report(500..=503).report(429..=429);
+        assert_eq!(
+            reported(&policy, &[400, 429, 500, 503, 504]),
+            [false, true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn ignored_statuses_take_precedence_over_ranges() {
+        let policy = ErrorPolicy::empty().ignore(503).report(500..=599);
+        assert_eq!(reported(&policy, &[500, 503]), [true, false]);
+    }
+
+    #[test]
+    fn custom_callback_takes_precedence() {
+        let policy = ErrorPolicy::default().custom(|_, response| match response.status().code {
+            200 => Some(true),
+            404 => Some(true),
+            500 => Some(false),
+            _ => None,
+        });
+        assert_eq!(
+            reported(&policy, &[200, 404, 500, 503, 302]),
+            [true, true, false, true, false]
+        );
+    }
+
+    #[test]
+    fn priorities_default_to_status_class() {
+        assert_eq!(
+            priorities(&ErrorPolicy::default(), &[400, 404, 500, 503]),
+            [DEFAULT_PRIORITY, DEFAULT_PRIORITY, 100, 100]
+        );
+    }
+
+    #[test]
+    fn later_priority_ranges_take_precedence() {
+        let policy = ErrorPolicy::empty()
+            .priority(400..=599, 10)
+            .priority(500..=599, 20)
+            .priority(503..=503, 30);
+        assert_eq!(
+            priorities(&policy, &[400, 500, 503, 600]),
+            [10, 20, 30, DEFAULT_PRIORITY]
+        );
+    }
+}
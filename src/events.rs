@@ -0,0 +1,234 @@
+//! Custom events, recorded alongside APM data for product analytics.
+
+use std::{error, fmt};
+
+use crate::Attribute;
+
+// New Relic's limits on custom events.
+const MAX_TYPE_LENGTH: usize = 255;
+const MAX_KEY_LENGTH: usize = 255;
+const MAX_VALUE_LENGTH: usize = 4096;
+const MAX_ATTRIBUTES: usize = 64;
+
+/// The attributes of a custom event.
+///
+/// ```
+/// use newrelic_fairing::events::EventAttributes;
+///
+/// let attributes = EventAttributes::new()
+///     .string("plan", "pro")
+///     .int("items", 3)
+///     .float("total", 42.5)
+///     .bool("first_order", true);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventAttributes(Vec<(String, Attribute)>);
+
+impl EventAttributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn string(self, key: &str, value: &str) -> Self {
+        self.attribute(key, Attribute::String(value.to_string()))
+    }
+
+    pub fn int(self, key: &str, value: i64) -> Self {
+        self.attribute(key, Attribute::Int(value))
+    }
+
+    pub fn float(self, key: &str, value: f64) -> Self {
+        self.attribute(key, Attribute::Float(value))
+    }
+
+    pub fn bool(self, key: &str, value: bool) -> Self {
+        self.attribute(key, Attribute::Bool(value))
+    }
+
+    /// Add an attribute of any type, replacing any previous value.
+    pub fn attribute<V: Into<Attribute>>(mut self, key: &str, value: V) -> Self {
+        self.0.retain(|(k, _)| k != key);
+        self.0.push((key.to_string(), value.into()));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn as_slice(&self) -> &[(String, Attribute)] {
+        &self.0
+    }
+}
+
+// Check a custom event against New Relic's limits.
+pub(crate) fn validate(event_type: &str, attributes: &EventAttributes) -> Result<(), EventError> {
+    let valid_type = !event_type.is_empty()
+        && event_type.len() <= MAX_TYPE_LENGTH
+        && event_type
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == ':' || c == '_' || c == ' ');
+    if !valid_type {
+        return Err(EventError::InvalidType(event_type.to_string()));
+    }
+    if attributes.len() > MAX_ATTRIBUTES {
+        return Err(EventError::TooManyAttributes);
+    }
+    for (key, value) in attributes.as_slice() {
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+            return Err(EventError::InvalidKey(key.clone()));
+        }
+        match value {
+            Attribute::String(s) if s.len() > MAX_VALUE_LENGTH => {
+                return Err(EventError::ValueTooLong(key.clone()))
+            }
+            Attribute::Float(f) if !f.is_finite() => {
+                return Err(EventError::NotFinite(key.clone()))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A custom event which New Relic would reject.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventError {
+    /// Event types must be at most 255 characters long, and only contain
+    /// letters, digits, `:`, `_` and spaces.
+    InvalidType(String),
+    /// Events can have at most 64 attributes.
+    TooManyAttributes,
+    /// Attribute keys must be between 1 and 255 bytes long.
+    InvalidKey(String),
+    /// The value of the attribute with this key was longer than 4096 bytes.
+    ValueTooLong(String),
+    /// The value of the attribute with this key was NaN or infinite.
+    NotFinite(String),
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventError::InvalidType(event_type) => {
+                write!(f, "invalid custom event type {:?}", event_type)
+            }
+            EventError::TooManyAttributes => write!(
+                f,
+                "custom events can have at most {} attributes",
+                MAX_ATTRIBUTES
+            ),
+            EventError::InvalidKey(key) => write!(f, "invalid event attribute key {:?}", key),
+            EventError::ValueTooLong(key) => write!(
+                f,
+                "value of event attribute {:?} is longer than {} bytes",
+                key, MAX_VALUE_LENGTH
+            ),
+            EventError::NotFinite(key) => {
+                write!(f, "value of event attribute {:?} is not finite", key)
+            }
+        }
+    }
+}
+
+impl error::Error for EventError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_events() {
+        let attributes = EventAttributes::new()
+            .string("plan", &"x".repeat(MAX_VALUE_LENGTH))
+            .int("items", 3)
+            .float("total", 42.5)
+            .bool("first_order", true);
+        assert_eq!(validate("Checkout:Completed_v2 ok", &attributes), Ok(()));
+        assert_eq!(validate(&"a".repeat(MAX_TYPE_LENGTH), &attributes), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid_types() {
+        let attributes = EventAttributes::new();
+        for event_type in &[
+            "",
+            "Checkout.Completed",
+            "Checkout-Completed",
+            "Café",
+            "a\tb",
+        ] {
+            assert_eq!(
+                validate(event_type, &attributes),
+                Err(EventError::InvalidType(event_type.to_string()))
+            );
+        }
+        let long = "a".repeat(MAX_TYPE_LENGTH + 1);
+        assert_eq!(
+            validate(&long, &attributes),
+            Err(EventError::InvalidType(long))
+        );
+    }
+
+    #[test]
+    fn limits_the_number_of_attributes() {
+        let attributes = (0..MAX_ATTRIBUTES).fold(EventAttributes::new(), |attributes, i| {
+            attributes.int(&format!("key{}", i), 1)
+        });
+        assert_eq!(validate("Event", &attributes), Ok(()));
+
+        // Replacing an attribute doesn't add another.
+        let attributes = attributes.int("key0", 2);
+        assert_eq!(validate("Event", &attributes), Ok(()));
+
+        let attributes = attributes.int("one_too_many", 1);
+        assert_eq!(
+            validate("Event", &attributes),
+            Err(EventError::TooManyAttributes)
+        );
+    }
+
+    #[test]
+    fn limits_key_lengths() {
+        let longest = "k".repeat(MAX_KEY_LENGTH);
+        let attributes = EventAttributes::new().int(&longest, 1);
+        assert_eq!(validate("Event", &attributes), Ok(()));
+
+        let attributes = EventAttributes::new().int("", 1);
+        assert_eq!(
+            validate("Event", &attributes),
+            Err(EventError::InvalidKey("".to_string()))
+        );
+
+        let long = "k".repeat(MAX_KEY_LENGTH + 1);
+        let attributes = EventAttributes::new().int(&long, 1);
+        assert_eq!(
+            validate("Event", &attributes),
+            Err(EventError::InvalidKey(long))
+        );
+    }
+
+    #[test]
+    fn limits_string_value_lengths() {
+        let attributes = EventAttributes::new().string("plan", &"x".repeat(MAX_VALUE_LENGTH + 1));
+        assert_eq!(
+            validate("Event", &attributes),
+            Err(EventError::ValueTooLong("plan".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_non_finite_floats() {
+        for value in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let attributes = EventAttributes::new().float("total", *value);
+            assert_eq!(
+                validate("Event", &attributes),
+                Err(EventError::NotFinite("total".to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! External segments for common HTTP clients.
+//!
+//! These are enabled by the `reqwest` feature.
+
+use crate::Transaction;
+
+impl Transaction {
+    /// Execute a request with reqwest's blocking client in an external
+    /// segment, adding distributed tracing headers to the request.
+    pub fn reqwest(
+        &self,
+        client: &reqwest::blocking::Client,
+        mut request: reqwest::blocking::Request,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let segment =
+            self.external_segment(request.url().as_str(), request.method().as_str(), "reqwest");
+
+        let headers = request.headers_mut();
+        for (name, value) in segment.distributed_trace_headers() {
+            if let Ok(value) = reqwest::header::HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+        client.execute(request)
+    }
+}
@@ -1,17 +1,263 @@
-use newrelic;
+use log::{error, warn};
+use std::sync::Arc;
 
 use rocket::{
-    fairing::{Fairing, Info, Kind},
-    request::{self, FromRequest},
-    Data, Outcome, Request, Response,
+    fairing::{AdHoc, Fairing, Info, Kind},
+    Data, Request, Response, Rocket,
 };
 
+mod app;
+mod attribute;
+pub mod backend;
+mod config;
+#[cfg(any(feature = "diesel", feature = "redis"))]
+mod datastore;
+pub mod distributed_tracing;
+mod error;
+mod error_detail;
+pub mod error_policy;
+pub mod events;
+#[cfg(feature = "reqwest")]
+mod external;
+pub mod naming;
+mod random;
+pub mod rules;
+mod segment;
+mod timing;
+pub mod trace_context;
+mod transaction;
+mod web;
+
+pub use crate::app::{BackgroundTransaction, NewRelicApp};
+pub use crate::attribute::{Attribute, AttributeError};
+pub use crate::config::{Config, LogLevel};
+pub use crate::error::Error;
+pub use crate::error_detail::{ErrorDetail, Reported};
+pub use crate::segment::Segment;
+pub use crate::transaction::Transaction;
+
+use crate::backend::{Backend, NewRelicBackend};
+use crate::distributed_tracing::{InboundTrace, TracePolicy};
+use crate::error_policy::ErrorPolicy;
+use crate::naming::{BaseAndHandler, TransactionNamer, WithMethod};
+use crate::rules::{Decided, Rule};
+use crate::trace_context::TraceContext;
+use crate::transaction::State;
+
 /// A fairing used to instrument requests with New Relic.
-pub struct NewRelic(newrelic::App);
+pub struct NewRelic {
+    app: NewRelicApp,
+    namer: Box<dyn TransactionNamer>,
+    include_query_string: bool,
+    error_policy: ErrorPolicy,
+    trace_policy: TracePolicy,
+    time_body_send: bool,
+    rules: Vec<Rule>,
+    exclusions: Vec<Rule>,
+}
 
 impl NewRelic {
+    /// Create a fairing which sends transactions to New Relic.
+    ///
+    /// If the New Relic app can't be created, a warning is logged and the
+    /// fairing is disabled. Use `try_new` to handle the error instead.
     pub fn new(app_name: &str, license_key: &str) -> Self {
-        NewRelic(newrelic::App::new(app_name, license_key).unwrap())
+        Self::or_disabled(Self::try_new(app_name, license_key))
+    }
+
+    /// Create a fairing which sends transactions to New Relic, returning
+    /// an error if the New Relic app can't be created.
+    pub fn try_new(app_name: &str, license_key: &str) -> Result<Self, Error> {
+        Self::from_config(&Config::new(app_name, license_key))
+    }
+
+    /// Create a fairing from the given settings.
+    ///
+    /// The fairing is disabled if `config.enabled` is false.
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        if !config.enabled {
+            return Ok(Self::disabled());
+        }
+        let newrelic = Self::with_backend(NewRelicBackend::from_config(config)?);
+        if config.name_with_method {
+            Ok(newrelic)
+        } else {
+            Ok(newrelic.naming(BaseAndHandler))
+        }
+    }
+
+    /// Create a fairing which reads its settings from Rocket's config.
+    ///
+    /// See `Config` for the available settings. Invalid settings are logged
+    /// and stop Rocket from launching. If the settings are valid but the
+    /// New Relic app can't be created, a warning is logged and the fairing
+    /// is disabled.
+    pub fn fairing() -> AdHoc {
+        Self::custom(|newrelic| newrelic)
+    }
+
+    /// Like `fairing`, but the fairing can be customized once it's been
+    /// created from Rocket's config:
+    ///
+    /// ```
+    /// use newrelic_fairing::{naming::RouteUri, NewRelic};
+    ///
+    /// rocket::ignite().attach(NewRelic::custom(|newrelic| newrelic.naming(RouteUri)));
+    /// ```
+    pub fn custom<F>(customize: F) -> AdHoc
+    where
+        F: FnOnce(Self) -> Self + Send + Sync + 'static,
+    {
+        AdHoc::on_attach("New Relic configuration", move |rocket| {
+            let config = match Config::from_rocket(rocket.config()) {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("{}", e);
+                    return Err(rocket);
+                }
+            };
+            let newrelic = Self::or_disabled(Self::from_config(&config));
+            Ok(rocket.attach(customize(newrelic)))
+        })
+    }
+
+    // Disable the fairing if it couldn't be created, rather than failing.
+    fn or_disabled(result: Result<Self, Error>) -> Self {
+        result.unwrap_or_else(|e| {
+            warn!("New Relic instrumentation disabled: {}", e);
+            Self::disabled()
+        })
+    }
+
+    /// Instrument requests using a custom backend, such as a
+    /// `RecordingBackend` in tests.
+    pub fn with_backend<B: Backend>(backend: B) -> Self {
+        Self::from_backend(Some(Arc::new(backend)))
+    }
+
+    /// Create a fairing which can be attached, but records nothing.
+    ///
+    /// Handlers using `&Transaction` as a request guard still work, but
+    /// their transactions go nowhere.
+    pub fn disabled() -> Self {
+        Self::from_backend(None)
+    }
+
+    fn from_backend(backend: Option<Arc<dyn Backend>>) -> Self {
+        NewRelic {
+            app: NewRelicApp::new(backend),
+            namer: Box::new(WithMethod(BaseAndHandler)),
+            include_query_string: false,
+            error_policy: ErrorPolicy::default(),
+            trace_policy: TracePolicy::default(),
+            time_body_send: false,
+            rules: Vec::new(),
+            exclusions: rules::default_exclusions(),
+        }
+    }
+
+    /// Set how transactions are named.
+    ///
+    /// Defaults to `WithMethod(BaseAndHandler)`, e.g. `GET api/get_user`.
+    pub fn naming<N: TransactionNamer>(mut self, namer: N) -> Self {
+        self.namer = Box::new(namer);
+        self
+    }
+
+    /// Set whether the `request.uri` attribute includes the query string.
+    ///
+    /// Defaults to false, since query strings often hold sensitive data.
+    pub fn include_query_string(mut self, include: bool) -> Self {
+        self.include_query_string = include;
+        self
+    }
+
+    /// Set which responses are reported as errors.
+    ///
+    /// Defaults to `ErrorPolicy::default()`.
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Set which requests' distributed tracing headers are accepted.
+    ///
+    /// Defaults to `TracePolicy::default()`.
+    pub fn trace_policy(mut self, policy: TracePolicy) -> Self {
+        self.trace_policy = policy;
+        self
+    }
+
+    /// Set whether transactions end after the response body has been sent,
+    /// rather than as soon as the response is ready.
+    ///
+    /// Either way, the time until the response was ready is added in the
+    /// `timing.handler` attribute. If this is set, the time taken to send
+    /// the body is added in a `Response/send body` segment and in the
+    /// `timing.bodySend` attribute, so that slow clients can be told apart
+    /// from slow handlers. Defaults to false.
+    pub fn time_body_send(mut self, time_body_send: bool) -> Self {
+        self.time_body_send = time_body_send;
+        self
+    }
+
+    /// Add a rule deciding whether matching requests are traced, sampled
+    /// or ignored.
+    ///
+    /// Rules are checked in the order they're added, and before the
+    /// default exclusions; see `Rule`.
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Set whether health checks and static files are ignored, using
+    /// `rules::default_exclusions`. Defaults to true.
+    ///
+    /// Individual exclusions can be overridden with rules instead,
+    /// e.g. `Rule::new(Action::Trace).path("/metrics")`.
+    pub fn default_exclusions(mut self, enabled: bool) -> Self {
+        self.exclusions = if enabled {
+            rules::default_exclusions()
+        } else {
+            Vec::new()
+        };
+        self
+    }
+
+    /// A handle to the fairing's New Relic app, for instrumenting work done
+    /// outside of requests.
+    ///
+    /// This is also added to Rocket's managed state when the fairing is
+    /// attached.
+    pub fn app(&self) -> &NewRelicApp {
+        &self.app
+    }
+
+    /// Whether the fairing records any transactions.
+    pub fn is_enabled(&self) -> bool {
+        self.app.is_enabled()
+    }
+
+    // The rules given to the fairing, followed by the default exclusions.
+    fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().chain(&self.exclusions)
+    }
+
+    // The name of the transaction for a request.
+    //
+    // Rocket keeps the last route tried even if every matching route
+    // forwarded and the 404 catcher responded, and this can't be told
+    // apart from the route's handler failing with a 404. So only requests
+    // which matched no route at all get catcher names; see `Transaction`.
+    fn transaction_name(&self, request: &Request, response: &Response) -> String {
+        match request.route() {
+            Some(route) => self.namer.name(route, request),
+            // No route matched, so the response came from a catcher.
+            // Name these by status alone, so that requests for arbitrary
+            // paths don't each get their own transaction.
+            None => format!("catcher/{}", response.status().code),
+        }
     }
 }
 
@@ -19,94 +265,107 @@ impl Fairing for NewRelic {
     fn info(&self) -> Info {
         Info {
             name: "New Relic instrumentation",
-            kind: Kind::Request | Kind::Response,
+            kind: Kind::Attach | Kind::Request | Kind::Response,
         }
     }
 
-    fn on_request(&self, request: &mut Request, _: &Data) {
-        request.local_cache(|| Transaction::new(&self.0, request));
+    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+        // Rocket panics if the same type is managed twice, so if another
+        // `NewRelic` fairing has already been attached, its app is kept.
+        if rocket.state::<NewRelicApp>().is_some() {
+            warn!("New Relic fairing attached more than once; keeping the first app handle");
+            return Ok(rocket);
+        }
+        Ok(rocket.manage(self.app.clone()))
     }
 
-    /// End the New Relic transaction, if it has been used in a request guard.
-    ///
-    /// Also adds an error code to the transaction if the response did
-    /// not succeed.
-    fn on_response(&self, request: &Request, response: &mut Response) {
-        match request.local_cache(|| Transaction::None) {
-            Transaction::Traced(transaction) => {
-                // Record any errors
-                let status = response.status();
-                if !status.class().is_success() {
-                    transaction.notice_error(100, &status.to_string(), "").ok();
-                }
-                // End the transaction explicitly here.
-                // Otherwise it ends after the response has finished being
-                // sent to the client, when it's dropped.
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        if let Some(backend) = self.app.backend() {
+            // Decide what to do with the request now if possible, so that
+            // transactions aren't started for requests which are ignored.
+            let action = rules::decide(self.rules(), request, None);
+            request.local_cache(|| Decided(action.is_some()));
+            let force_trace = match action {
+                Some(action) if !action.should_trace() => return,
+                Some(_) => true,
+                None => false,
+            };
 
-                // **This doesn't work** because `Transaction::end` requires `&mut self`
-                transaction.end();
+            // Continue the trace from the upstream service, if any.
+            let inbound = if self.trace_policy.accepts(request) {
+                InboundTrace::from_request(request)
+            } else {
+                None
+            };
+            let trace_context = TraceContext::from_inbound(inbound.as_ref());
+
+            // Requests haven't been routed yet, so the transaction is
+            // renamed once the response is ready.
+            let transaction =
+                request.local_cache(|| Transaction::new(backend, "unknown_handler", trace_context));
+            if let Some(trace) = &inbound {
+                transaction.with_transaction(|t| t.accept_distributed_trace(trace));
             }
-            Transaction::NotTraced(transaction) => {
-                transaction.ignore();
+            if force_trace {
+                transaction.trace();
             }
-            _ => {}
         }
     }
-}
 
-pub enum Transaction {
-    /// A running and traced New Relic transaction.
-    /// Will be sent to New Relic.
-    Traced(newrelic::Transaction),
-
-    /// A running, but not traced, New Relic transaction.
-    /// Will be ignored rather than ended.
-    NotTraced(newrelic::Transaction),
+    /// End the New Relic transaction, if it has been used in a request guard.
+    ///
+    /// Also adds the standard web request attributes to the transaction,
+    /// and an error code if the error policy says the response failed.
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        // Take ownership of the transaction so that it can be ended (or
+        // ignored) here, before the response body is sent to the client,
+        // unless the time taken to send the body is being recorded too.
+        // Otherwise it would end when the request-local cache is dropped.
+        let cached = request.local_cache(Transaction::none);
 
-    /// A dummy transaction; used if the New Relic SDK
-    /// returns an error.
-    None,
-}
+        // Apply any rules which needed to know the route.
+        if !request.local_cache(|| Decided(false)).0 {
+            match rules::decide(self.rules(), request, request.route()) {
+                Some(action) if action.should_trace() => cached.trace(),
+                Some(_) => cached.untrace(),
+                None => {}
+            }
+        }
 
-impl Transaction {
-    // Create a new transaction for a request.
-    //
-    // The New Relic transaction will have the URL and transaction name
-    // attributes set.
-    fn new(app: &newrelic::App, request: &Request) -> Self {
-        // Use the route handler as the transaction name.
-        // This should always be used inside a request guard so that
-        // request.route() is not None.
-        let transaction_name: String = request
-            .route()
-            .map(|r| {
-                format!(
-                    "{}/{}",
-                    r.base.to_string().trim_start_matches('/'),
-                    r.name.unwrap_or("unknown_handler")
-                )
-            })
-            .unwrap_or_else(|| "unknown_handler".to_string());
-
-        // Start a new transaction. Note that this will be ignored
-        // unless it's used in a request guard.
-        app.web_transaction(&transaction_name)
-            .map(|t| Transaction::NotTraced(t))
-            .unwrap_or(Transaction::None)
-    }
-}
+        match cached.take() {
+            State::Traced(mut transaction) => {
+                transaction.set_name(&self.transaction_name(request, response));
+                web::add_attributes(
+                    &mut *transaction,
+                    request,
+                    response,
+                    self.include_query_string,
+                );
+                cached.trace_context().add_attributes(&mut *transaction);
 
-impl<'a, 'r> FromRequest<'a, 'r> for &'a Transaction {
-    type Error = ();
+                // Record any errors
+                if self.error_policy.is_error(request, response) {
+                    let status = response.status().code;
+                    let priority = self.error_policy.priority_for(request, status);
+                    let detail = ErrorDetail::take(request)
+                        .unwrap_or_else(|| ErrorDetail::from_response(response));
+                    transaction.notice_error(priority, &detail.message, &detail.class);
+                }
 
-    // Switch the transaction from a NonTraced to a Traced transaction.
-    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
-        let transaction = request.local_cache(|| Transaction::None);
-        if let Transaction::NotTraced(t) = transaction {
-            // This doesn't work because we don't own, and can't mutate,
-            // the request-local cache.
-            *transaction = Transaction::Traced(*t);
+                timing::add_handler_time(&mut *transaction, cached.started());
+                if self.time_body_send {
+                    timing::end_after_body(transaction, response);
+                } else {
+                    transaction.end();
+                }
+            }
+            State::NotTraced(mut transaction) => {
+                // Named anyway, so that ignored transactions can be told
+                // apart by backends which record them.
+                transaction.set_name(&self.transaction_name(request, response));
+                transaction.ignore();
+            }
+            State::None => {}
         }
-        Outcome::Success(transaction)
     }
 }
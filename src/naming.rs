@@ -0,0 +1,67 @@
+//! Strategies for naming transactions after the route that handled them.
+
+use rocket::{Request, Route};
+
+/// Decides the name of the transaction for a request.
+pub trait TransactionNamer: Send + Sync + 'static {
+    /// The name of the transaction for a request handled by `route`.
+    fn name(&self, route: &Route, request: &Request) -> String;
+}
+
+/// Name transactions after the route's method and URI template,
+/// e.g. `GET /users/<id>`.
+pub struct RouteUri;
+
+impl TransactionNamer for RouteUri {
+    fn name(&self, route: &Route, _: &Request) -> String {
+        format!("{} {}", route.method, route.uri.path())
+    }
+}
+
+/// Name transactions after the route's handler function, e.g. `get_user`.
+pub struct HandlerName;
+
+impl TransactionNamer for HandlerName {
+    fn name(&self, route: &Route, _: &Request) -> String {
+        route.name.unwrap_or("unknown_handler").to_string()
+    }
+}
+
+/// Name transactions after the route's mount point and handler function,
+/// e.g. `api/get_user`.
+///
+/// By default this is wrapped in `WithMethod`.
+pub struct BaseAndHandler;
+
+impl TransactionNamer for BaseAndHandler {
+    fn name(&self, route: &Route, _: &Request) -> String {
+        format!(
+            "{}/{}",
+            route.base.to_string().trim_start_matches('/'),
+            route.name.unwrap_or("unknown_handler")
+        )
+    }
+}
+
+/// Prefix the names given by another namer with the request method,
+/// e.g. `GET api/get_user`.
+///
+/// This keeps requests with different methods in separate transactions,
+/// even when they're handled by functions with the same name.
+pub struct WithMethod<N>(pub N);
+
+impl<N: TransactionNamer> TransactionNamer for WithMethod<N> {
+    fn name(&self, route: &Route, request: &Request) -> String {
+        format!("{} {}", route.method, self.0.name(route, request))
+    }
+}
+
+/// Name transactions using a closure.
+impl<F> TransactionNamer for F
+where
+    F: Fn(&Route, &Request) -> String + Send + Sync + 'static,
+{
+    fn name(&self, route: &Route, request: &Request) -> String {
+        self(route, request)
+    }
+}
@@ -0,0 +1,23 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::SystemTime,
+};
+
+// A random number.
+//
+// These only need to be unique or evenly spread, not unpredictable, so
+// the standard library's randomly seeded hasher is enough.
+pub(crate) fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    hasher.finish()
+}
+
+// A random number in `[0, 1)`.
+pub(crate) fn random_f64() -> f64 {
+    // Use the top 53 bits, which an f64 can represent exactly.
+    (random_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,423 @@
+//! Rules deciding which requests are traced, sampled or ignored.
+
+use rocket::{http::Method, Request, Route};
+
+use crate::random::random_f64;
+
+/// What to do with a request matched by a rule.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Send the transaction to New Relic, even if the handler doesn't use
+    /// `&Transaction` as a request guard.
+    Trace,
+    /// Trace the given fraction of requests, between 0 and 1, and ignore
+    /// the rest.
+    Sample(f64),
+    /// Never send the transaction to New Relic.
+    Ignore,
+}
+
+/// A rule matching requests by path, method, header and route.
+///
+/// A rule matches a request if all of its conditions do. The first rule
+/// matching a request decides what happens to it; requests which no rule
+/// matches are only traced if the handler uses `&Transaction` as a
+/// request guard.
+///
+/// ```
+/// use newrelic_fairing::rules::{Action, Rule};
+/// use rocket::http::Method;
+///
+/// let rules = vec![
+///     Rule::new(Action::Ignore).path("/static/**"),
+///     Rule::new(Action::Trace).header("X-Debug"),
+///     Rule::new(Action::Sample(0.1)).method(Method::Get).route("list_items"),
+/// ];
+/// ```
+#[derive(Clone, Debug)]
+pub struct Rule {
+    action: Action,
+    path: Option<String>,
+    method: Option<Method>,
+    header: Option<(String, Option<String>)>,
+    route: Option<RouteCondition>,
+}
+
+#[derive(Clone, Debug)]
+enum RouteCondition {
+    Named(String),
+    Unnamed,
+}
+
+// Whether a rule matches a request. Rules with route conditions can't be
+// matched before the request has been routed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Match {
+    Yes,
+    No,
+    Unknown,
+}
+
+impl Rule {
+    /// A rule matching every request, until conditions are added.
+    pub fn new(action: Action) -> Self {
+        Rule {
+            action,
+            path: None,
+            method: None,
+            header: None,
+            route: None,
+        }
+    }
+
+    /// Only match requests whose path matches a glob, where `*` matches
+    /// within a path segment and `**` matches any number of segments,
+    /// e.g. `/static/**` or `/users/*/avatar`.
+    pub fn path(mut self, glob: &str) -> Self {
+        self.path = Some(glob.to_string());
+        self
+    }
+
+    /// Only match requests with the given method.
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = Some(method);
+        self
+    }
+
+    /// Only match requests with the given header.
+    pub fn header(mut self, name: &str) -> Self {
+        self.header = Some((name.to_string(), None));
+        self
+    }
+
+    /// Only match requests with the given header set to the given value.
+    pub fn header_value(mut self, name: &str, value: &str) -> Self {
+        self.header = Some((name.to_string(), Some(value.to_string())));
+        self
+    }
+
+    /// Only match requests handled by the named route. Routes are named
+    /// after their handler functions.
+    pub fn route(mut self, name: &str) -> Self {
+        self.route = Some(RouteCondition::Named(name.to_string()));
+        self
+    }
+
+    /// Only match requests handled by routes without names, such as those
+    /// created by `rocket_contrib`'s `StaticFiles`. Routes created by
+    /// Rocket's attributes, like `#[get]`, always have names.
+    pub fn unnamed_route(mut self) -> Self {
+        self.route = Some(RouteCondition::Unnamed);
+        self
+    }
+
+    fn matches(&self, request: &Request, route: Option<&Route>) -> Match {
+        let path_matches = self
+            .path
+            .as_ref()
+            .map(|glob| glob_matches(glob, request.uri().path()))
+            .unwrap_or(true);
+        let method_matches = self
+            .method
+            .map(|method| method == request.method())
+            .unwrap_or(true);
+        let header_matches = match &self.header {
+            None => true,
+            Some((name, None)) => request.headers().contains(name),
+            Some((name, Some(value))) => request.headers().get(name).any(|v| v == value.as_str()),
+        };
+        if !(path_matches && method_matches && header_matches) {
+            return Match::No;
+        }
+        match (&self.route, route) {
+            (None, _) => Match::Yes,
+            (Some(_), None) => Match::Unknown,
+            (Some(RouteCondition::Named(name)), Some(route)) => {
+                match_if(route.name == Some(name.as_str()))
+            }
+            (Some(RouteCondition::Unnamed), Some(route)) => match_if(route.name.is_none()),
+        }
+    }
+}
+
+fn match_if(matches: bool) -> Match {
+    if matches {
+        Match::Yes
+    } else {
+        Match::No
+    }
+}
+
+/// Rules ignoring health checks and static files: requests for `/health`,
+/// `/ping`, `/metrics` and `/favicon.ico`, and requests handled by
+/// unnamed routes, such as those created by `StaticFiles`.
+///
+/// The fairing uses these by default, after any rules it's been given.
+pub fn default_exclusions() -> Vec<Rule> {
+    let mut rules: Vec<Rule> = ["/health", "/ping", "/metrics", "/favicon.ico"]
+        .iter()
+        .map(|path| Rule::new(Action::Ignore).path(path))
+        .collect();
+    rules.push(
+        Rule::new(Action::Ignore)
+            .method(Method::Get)
+            .unnamed_route(),
+    );
+    rules
+}
+
+// Marks whether the rules were decided before the request was routed.
+pub(crate) struct Decided(pub(crate) bool);
+
+// The action of the first rule matching a request.
+//
+// Before the request has been routed, `route` is `None`, and no decision
+// is made if a rule with a route condition might match first.
+pub(crate) fn decide<'a, I>(rules: I, request: &Request, route: Option<&Route>) -> Option<Action>
+where
+    I: IntoIterator<Item = &'a Rule>,
+{
+    for rule in rules {
+        match rule.matches(request, route) {
+            Match::Yes => return Some(rule.action),
+            Match::No => {}
+            Match::Unknown => return None,
+        }
+    }
+    None
+}
+
+impl Action {
+    // Whether a request with this action should be traced, rolling the
+    // dice for sampled requests.
+    pub(crate) fn should_trace(self) -> bool {
+        match self {
+            Action::Trace => true,
+            Action::Sample(rate) => random_f64() < rate,
+            Action::Ignore => false,
+        }
+    }
+}
+
+// Match a path against a glob, where `*` matches within a segment and
+// `**` matches any number of whole segments.
+fn glob_matches(glob: &str, path: &str) -> bool {
+    let glob: Vec<&str> = glob.trim_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_matches('/').split('/').collect();
+    segments_match(&glob, &path)
+}
+
+fn segments_match(glob: &[&str], path: &[&str]) -> bool {
+    match (glob.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((&"**", rest)), _) => {
+            // Try matching the rest of the glob against every suffix.
+            (0..=path.len()).any(|skip| segments_match(rest, &path[skip..]))
+        }
+        (Some((pattern, glob_rest)), Some((segment, path_rest))) => {
+            segment_matches(pattern, segment) && segments_match(glob_rest, path_rest)
+        }
+        _ => false,
+    }
+}
+
+// Match a single path segment against a pattern, where `*` matches any
+// number of characters.
+fn segment_matches(pattern: &str, segment: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern == segment,
+        Some(star) => {
+            let (prefix, rest) = (&pattern[..star], &pattern[star + 1..]);
+            segment.starts_with(prefix)
+                && (prefix.len()..=segment.len()).any(|start| {
+                    segment.is_char_boundary(start) && segment_matches(rest, &segment[start..])
+                })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        handler::Outcome,
+        http::{Header, Method},
+        local::Client,
+        Data, Request, Route,
+    };
+
+    use super::*;
+
+    fn handler<'r>(request: &'r Request, _: Data) -> Outcome<'r> {
+        Outcome::from(request, "ok")
+    }
+
+    fn route(name: Option<&'static str>) -> Route {
+        let mut route = Route::new(Method::Get, "/items", handler);
+        route.name = name;
+        route
+    }
+
+    fn client() -> Client {
+        Client::new(rocket::ignite()).unwrap()
+    }
+
+    #[test]
+    fn matches_literal_paths() {
+        assert!(glob_matches("/health", "/health"));
+        assert!(glob_matches("/", "/"));
+        assert!(!glob_matches("/health", "/healthz"));
+        assert!(!glob_matches("/health", "/health/db"));
+        assert!(!glob_matches("/health", "/"));
+    }
+
+    #[test]
+    fn ignores_trailing_slashes() {
+        assert!(glob_matches("/health", "/health/"));
+        assert!(glob_matches("/health/", "/health"));
+        assert!(glob_matches("/static/**", "/static/"));
+    }
+
+    #[test]
+    fn star_matches_within_a_segment() {
+        assert!(glob_matches("/users/*/avatar", "/users/42/avatar"));
+        assert!(!glob_matches("/users/*/avatar", "/users/42/x/avatar"));
+        assert!(!glob_matches("/users/*", "/users"));
+        assert!(glob_matches("/files/*.png", "/files/logo.png"));
+        assert!(glob_matches("/files/*.png", "/files/.png"));
+        assert!(!glob_matches("/files/*.png", "/files/logo.jpg"));
+        assert!(glob_matches("/v*/items", "/v2/items"));
+        assert!(glob_matches("/*-*", "/a-b-c"));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_segments() {
+        assert!(glob_matches("/static/**", "/static"));
+        assert!(glob_matches("/static/**", "/static/app.js"));
+        assert!(glob_matches("/static/**", "/static/js/vendor/app.js"));
+        assert!(!glob_matches("/static/**", "/assets/app.js"));
+        assert!(glob_matches("/api/**/health", "/api/health"));
+        assert!(glob_matches("/api/**/health", "/api/v1/users/health"));
+        assert!(!glob_matches("/api/**/health", "/api/v1/healthz"));
+        assert!(glob_matches("/**", "/anything/at/all"));
+        assert!(glob_matches("/**/*.ico", "/favicon.ico"));
+    }
+
+    #[test]
+    fn matches_multibyte_segments() {
+        assert!(glob_matches("/café/*", "/café/menu"));
+        assert!(glob_matches("/*é", "/ééé"));
+        assert!(glob_matches("/é*é", "/éé"));
+        assert!(glob_matches("/a*b", "/a日本b"));
+        assert!(!glob_matches("/a*b", "/a日本"));
+        assert!(glob_matches("/*", "/日本"));
+        assert!(!glob_matches("/日*", "/本日"));
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let client = client();
+        let request = client.get("/items").header(Header::new("X-Debug", "1"));
+        let rules = vec![
+            Rule::new(Action::Ignore).path("/other"),
+            Rule::new(Action::Trace).header("X-Debug"),
+            Rule::new(Action::Ignore).path("/items"),
+        ];
+
+        assert_eq!(decide(&rules, request.inner(), None), Some(Action::Trace));
+    }
+
+    #[test]
+    fn matches_all_conditions() {
+        let client = client();
+        let rules = vec![Rule::new(Action::Ignore)
+            .path("/items")
+            .method(Method::Get)
+            .header_value("X-Debug", "off")];
+
+        let request = client.get("/items").header(Header::new("X-Debug", "off"));
+        assert_eq!(decide(&rules, request.inner(), None), Some(Action::Ignore));
+
+        let request = client.get("/items").header(Header::new("X-Debug", "on"));
+        assert_eq!(decide(&rules, request.inner(), None), None);
+
+        let request = client.post("/items").header(Header::new("X-Debug", "off"));
+        assert_eq!(decide(&rules, request.inner(), None), None);
+    }
+
+    #[test]
+    fn route_rules_before_path_rules_wait_for_routing() {
+        let client = client();
+        let request = client.get("/items");
+        let rules = vec![
+            Rule::new(Action::Trace).route("list_items"),
+            Rule::new(Action::Ignore).path("/items"),
+        ];
+
+        // The route rule might match, so nothing is decided yet.
+        assert_eq!(decide(&rules, request.inner(), None), None);
+
+        let list_items = route(Some("list_items"));
+        assert_eq!(
+            decide(&rules, request.inner(), Some(&list_items)),
+            Some(Action::Trace)
+        );
+
+        let other = route(Some("other"));
+        assert_eq!(
+            decide(&rules, request.inner(), Some(&other)),
+            Some(Action::Ignore)
+        );
+    }
+
+    #[test]
+    fn route_rules_which_cant_match_dont_wait_for_routing() {
+        let client = client();
+        let request = client.get("/items");
+        let rules = vec![
+            Rule::new(Action::Trace)
+                .method(Method::Post)
+                .route("add_item"),
+            Rule::new(Action::Ignore).path("/items"),
+        ];
+
+        assert_eq!(decide(&rules, request.inner(), None), Some(Action::Ignore));
+    }
+
+    #[test]
+    fn path_rules_before_route_rules_decide_before_routing() {
+        let client = client();
+        let request = client.get("/items");
+        let rules = vec![
+            Rule::new(Action::Ignore).path("/items"),
+            Rule::new(Action::Trace).route("list_items"),
+        ];
+
+        assert_eq!(decide(&rules, request.inner(), None), Some(Action::Ignore));
+    }
+
+    #[test]
+    fn default_exclusions_ignore_unnamed_routes() {
+        let client = client();
+        let rules = default_exclusions();
+
+        let request = client.get("/health");
+        assert_eq!(decide(&rules, request.inner(), None), Some(Action::Ignore));
+
+        let request = client.get("/items");
+        assert_eq!(decide(&rules, request.inner(), None), None);
+        assert_eq!(
+            decide(&rules, request.inner(), Some(&route(None))),
+            Some(Action::Ignore)
+        );
+        assert_eq!(
+            decide(&rules, request.inner(), Some(&route(Some("list_items")))),
+            None
+        );
+    }
+
+    #[test]
+    fn sample_rates_of_zero_and_one_are_exact() {
+        assert!((0..100).all(|_| Action::Sample(1.0).should_trace()));
+        assert!((0..100).all(|_| !Action::Sample(0.0).should_trace()));
+    }
+}
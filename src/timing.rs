@@ -0,0 +1,82 @@
+use std::{
+    io::{self, Read},
+    time::{Duration, Instant},
+};
+
+use rocket::{response::Body, Response};
+
+use crate::backend::{BackendTransaction, SegmentId, SegmentParams};
+
+// Add the time taken from the start of the request until the response
+// was ready, which includes routing, request guards and the handler.
+pub(crate) fn add_handler_time(transaction: &mut dyn BackendTransaction, started: Instant) {
+    transaction.add_attribute("timing.handler", &seconds(started.elapsed()).into());
+}
+
+// End the transaction once the response body has been sent, timing how
+// long sending it took in a segment and an attribute.
+pub(crate) fn end_after_body(
+    mut transaction: Box<dyn BackendTransaction>,
+    response: &mut Response,
+) {
+    let params = SegmentParams::Custom {
+        name: "send body".to_string(),
+        category: "Response".to_string(),
+    };
+    let segment = transaction.start_segment(&params, None);
+    let sent = SentBody {
+        transaction: Some(transaction),
+        segment,
+        started: Instant::now(),
+    };
+    match response.take_body() {
+        Some(Body::Sized(body, size)) => response.set_raw_body(Body::Sized(sent.wrap(body), size)),
+        Some(Body::Chunked(body, size)) => {
+            response.set_raw_body(Body::Chunked(sent.wrap(body), size))
+        }
+        // Ends the transaction straight away.
+        None => drop(sent),
+    }
+}
+
+// Ends a transaction when dropped, after its response body has been sent.
+struct SentBody {
+    transaction: Option<Box<dyn BackendTransaction>>,
+    segment: Option<SegmentId>,
+    started: Instant,
+}
+
+impl SentBody {
+    fn wrap<R: Read>(self, body: R) -> TimedBody<R> {
+        TimedBody { body, _sent: self }
+    }
+}
+
+impl Drop for SentBody {
+    fn drop(&mut self) {
+        if let Some(mut transaction) = self.transaction.take() {
+            if let Some(id) = self.segment {
+                transaction.end_segment(id);
+            }
+            transaction.add_attribute("timing.bodySend", &seconds(self.started.elapsed()).into());
+            transaction.end();
+        }
+    }
+}
+
+// A response body which ends its transaction once it's been sent.
+struct TimedBody<R> {
+    body: R,
+    // Only held to be dropped along with the body.
+    _sent: SentBody,
+}
+
+impl<R: Read> Read for TimedBody<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
@@ -0,0 +1,456 @@
+//! W3C Trace Context support.
+//!
+//! Incoming `traceparent` and `tracestate` headers are parsed according to
+//! <https://www.w3.org/TR/trace-context/>, and propagated to outgoing
+//! requests, independently of New Relic's own `newrelic` header.
+
+use std::fmt::Write;
+
+use crate::{backend::BackendTransaction, distributed_tracing::InboundTrace, random::random_u64};
+
+// The version of the spec used for outgoing headers.
+const VERSION: u8 = 0;
+const SAMPLED: u8 = 0x01;
+const MAX_TRACESTATE_MEMBERS: usize = 32;
+
+/// A parsed `traceparent` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceParent {
+    version: u8,
+    trace_id: String,
+    parent_id: String,
+    flags: u8,
+}
+
+impl TraceParent {
+    /// Parse a `traceparent` header, returning `None` if it's invalid.
+    ///
+    /// Headers with versions later than `00` are accepted if they start
+    /// with the fields defined by version `00`.
+    pub fn parse(header: &str) -> Option<Self> {
+        let header = header.trim();
+        // Checked first so that slicing by byte index below can't panic.
+        if !header.is_ascii() || header.len() < 55 {
+            return None;
+        }
+        let version = parse_hex_byte(&header[..2])?;
+        // Version ff is forbidden.
+        if version == 0xff {
+            return None;
+        }
+        // Version 00 has exactly four fields; later versions may add more.
+        if header.len() > 55 && (version == VERSION || header.as_bytes()[55] != b'-') {
+            return None;
+        }
+
+        let mut fields = header[..55].split('-');
+        let _version = fields.next()?;
+        let trace_id = fields.next().filter(|id| is_id(id, 32))?;
+        let parent_id = fields.next().filter(|id| is_id(id, 16))?;
+        let flags = parse_hex_byte(fields.next()?)?;
+
+        Some(TraceParent {
+            version,
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+            flags,
+        })
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The 32 character hex ID of the whole trace.
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+
+    /// The 16 character hex ID of the caller's span.
+    pub fn parent_id(&self) -> &str {
+        &self.parent_id
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Whether the caller may have recorded its part of the trace.
+    pub fn sampled(&self) -> bool {
+        self.flags & SAMPLED != 0
+    }
+}
+
+/// A parsed `tracestate` header: vendor-specific key-value pairs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceState(Vec<(String, String)>);
+
+impl TraceState {
+    /// Parse a `tracestate` header, returning `None` if it's invalid.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut members: Vec<(String, String)> = Vec::new();
+        // Empty list members are allowed, and ignored.
+        for member in header.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+            let mut parts = member.splitn(2, '=');
+            let key = parts.next().filter(|k| is_tracestate_key(k))?;
+            let value = parts.next().filter(|v| is_tracestate_value(v))?;
+            // Keys must be unique.
+            if members.iter().any(|(k, _)| k == key) {
+                return None;
+            }
+            members.push((key.to_string(), value.to_string()));
+        }
+        if members.len() > MAX_TRACESTATE_MEMBERS {
+            return None;
+        }
+        Some(TraceState(members))
+    }
+
+    /// The value for a vendor's key, if present.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn to_header(&self) -> String {
+        self.0
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// The W3C trace that a transaction is part of.
+///
+/// If a request didn't continue a trace from an upstream service, the
+/// transaction starts a new one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: String,
+    flags: u8,
+    parent: Option<TraceParent>,
+    state: Option<TraceState>,
+}
+
+impl TraceContext {
+    // The trace context continued from an upstream service, or a new one.
+    pub(crate) fn from_inbound(trace: Option<&InboundTrace>) -> Self {
+        let parent = trace
+            .and_then(|t| t.traceparent.as_ref())
+            .and_then(|header| TraceParent::parse(header));
+        match parent {
+            Some(parent) => TraceContext {
+                trace_id: parent.trace_id.clone(),
+                flags: parent.flags,
+                // An invalid tracestate is discarded, but the traceparent
+                // is still used.
+                state: trace
+                    .and_then(|t| t.tracestate.as_ref())
+                    .and_then(|header| TraceState::parse(header))
+                    .filter(|state| !state.is_empty()),
+                parent: Some(parent),
+            },
+            None => TraceContext::new(),
+        }
+    }
+
+    // Start a new trace.
+    pub(crate) fn new() -> Self {
+        TraceContext {
+            trace_id: random_id(16),
+            flags: SAMPLED,
+            parent: None,
+            state: None,
+        }
+    }
+
+    /// The 32 character hex ID of the trace, for correlating logs.
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+
+    /// The `traceparent` sent by the upstream service, if any.
+    pub fn parent(&self) -> Option<&TraceParent> {
+        self.parent.as_ref()
+    }
+
+    /// The `tracestate` sent by the upstream service, if any.
+    pub fn state(&self) -> Option<&TraceState> {
+        self.state.as_ref()
+    }
+
+    /// Whether the trace may be recorded by the services taking part.
+    pub fn sampled(&self) -> bool {
+        self.flags & SAMPLED != 0
+    }
+
+    // Headers continuing this trace in an outgoing request, with a new
+    // span ID for the outgoing call.
+    pub(crate) fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![(
+            "traceparent",
+            format!(
+                "{:02x}-{}-{}-{:02x}",
+                VERSION,
+                self.trace_id,
+                random_id(8),
+                self.flags
+            ),
+        )];
+        if let Some(state) = &self.state {
+            headers.push(("tracestate", state.to_header()));
+        }
+        headers
+    }
+
+    // Add the trace's IDs to a transaction.
+    pub(crate) fn add_attributes(&self, transaction: &mut dyn BackendTransaction) {
+        transaction.add_attribute("w3c.traceId", &self.trace_id.as_str().into());
+        if let Some(parent) = &self.parent {
+            transaction.add_attribute("w3c.parentId", &parent.parent_id.as_str().into());
+        }
+        transaction.add_attribute("w3c.sampled", &self.sampled().into());
+    }
+}
+
+// A lowercase hex ID of the given length which isn't all zeros.
+fn is_id(id: &str, length: usize) -> bool {
+    id.len() == length && is_lower_hex(id) && id.bytes().any(|b| b != b'0')
+}
+
+fn is_lower_hex(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn parse_hex_byte(s: &str) -> Option<u8> {
+    if s.len() == 2 && is_lower_hex(s) {
+        u8::from_str_radix(s, 16).ok()
+    } else {
+        None
+    }
+}
+
+// Keys are either `key` or `tenant@system`, made of lowercase letters,
+// digits, and `_-*/`.
+fn is_tracestate_key(key: &str) -> bool {
+    fn is_key_char(c: char) -> bool {
+        c.is_ascii_lowercase() || c.is_ascii_digit() || "_-*/".contains(c)
+    }
+    fn is_part(part: &str, max: usize) -> bool {
+        !part.is_empty()
+            && part.len() <= max
+            && part
+                .chars()
+                .next()
+                .map(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+                .unwrap_or(false)
+            && part.chars().all(is_key_char)
+    }
+    let mut parts = key.splitn(2, '@');
+    match (parts.next(), parts.next()) {
+        (Some(key), None) => is_part(key, 256),
+        (Some(tenant), Some(system)) => is_part(tenant, 241) && is_part(system, 14),
+        _ => false,
+    }
+}
+
+// Values are up to 256 printable ASCII characters, excluding `,` and `=`,
+// and can't end with a space.
+fn is_tracestate_value(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 256
+        && !value.ends_with(' ')
+        && value
+            .bytes()
+            .all(|b| (0x20..=0x7e).contains(&b) && b != b',' && b != b'=')
+}
+
+// A random, non-zero, lowercase hex ID of the given number of bytes.
+fn random_id(bytes: usize) -> String {
+    let mut id = String::with_capacity(bytes * 2);
+    while id.len() < bytes * 2 {
+        let value = random_u64();
+        if value != 0 {
+            write!(id, "{:016x}", value).expect("writing to a String can't fail");
+        }
+    }
+    id.truncate(bytes * 2);
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const PARENT_ID: &str = "00f067aa0ba902b7";
+
+    fn traceparent(version: &str, trace_id: &str, parent_id: &str, flags: &str) -> String {
+        format!("{}-{}-{}-{}", version, trace_id, parent_id, flags)
+    }
+
+    #[test]
+    fn parses_valid_traceparent() {
+        let parent = TraceParent::parse(&traceparent("00", TRACE_ID, PARENT_ID, "01")).unwrap();
+        assert_eq!(parent.version(), 0);
+        assert_eq!(parent.trace_id(), TRACE_ID);
+        assert_eq!(parent.parent_id(), PARENT_ID);
+        assert!(parent.sampled());
+
+        let parent = TraceParent::parse(&traceparent("00", TRACE_ID, PARENT_ID, "00")).unwrap();
+        assert!(!parent.sampled());
+    }
+
+    #[test]
+    fn trims_traceparent_whitespace() {
+        let header = format!("  {}\t", traceparent("00", TRACE_ID, PARENT_ID, "01"));
+        assert!(TraceParent::parse(&header).is_some());
+    }
+
+    #[test]
+    fn rejects_invalid_traceparent() {
+        let invalid = [
+            String::new(),
+            "00".to_string(),
+            traceparent("00", TRACE_ID, PARENT_ID, "0"),
+            traceparent("00", &TRACE_ID.to_uppercase(), PARENT_ID, "01"),
+            traceparent("00", TRACE_ID, &PARENT_ID[1..], "01x"),
+            traceparent("0g", TRACE_ID, PARENT_ID, "01"),
+            traceparent("00", TRACE_ID, PARENT_ID, "zz"),
+            traceparent("00", TRACE_ID, PARENT_ID, "01").replace('-', "_"),
+            // Version 00 can't have extra fields.
+            format!("{}-extra", traceparent("00", TRACE_ID, PARENT_ID, "01")),
+            // Version ff is forbidden.
+            traceparent("ff", TRACE_ID, PARENT_ID, "01"),
+        ];
+        for header in &invalid {
+            assert_eq!(TraceParent::parse(header), None, "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn accepts_future_versions() {
+        let header = traceparent("01", TRACE_ID, PARENT_ID, "01");
+        assert_eq!(TraceParent::parse(&header).unwrap().version(), 1);
+
+        let extended = format!("{}-future", header);
+        assert_eq!(TraceParent::parse(&extended).unwrap().trace_id(), TRACE_ID);
+
+        // Extra fields must still be separated by a dash.
+        let joined = format!("{}future", header);
+        assert_eq!(TraceParent::parse(&joined), None);
+    }
+
+    #[test]
+    fn rejects_all_zero_ids() {
+        let zero_trace = "0".repeat(32);
+        let zero_parent = "0".repeat(16);
+        assert_eq!(
+            TraceParent::parse(&traceparent("00", &zero_trace, PARENT_ID, "01")),
+            None
+        );
+        assert_eq!(
+            TraceParent::parse(&traceparent("00", TRACE_ID, &zero_parent, "01")),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_non_ascii_traceparent() {
+        let header = format!("€{}", "0".repeat(55));
+        assert_eq!(TraceParent::parse(&header), None);
+
+        let header = format!("0€{}", "0".repeat(55));
+        assert_eq!(TraceParent::parse(&header), None);
+
+        let header = traceparent("00", TRACE_ID, PARENT_ID, "01").replacen('0', "é", 1);
+        assert_eq!(TraceParent::parse(&header), None);
+    }
+
+    #[test]
+    fn parses_tracestate() {
+        let state = TraceState::parse("congo=t61rcWkgMzE, rojo=00f067aa0ba902b7").unwrap();
+        assert_eq!(state.get("congo"), Some("t61rcWkgMzE"));
+        assert_eq!(state.get("rojo"), Some("00f067aa0ba902b7"));
+        assert_eq!(state.get("missing"), None);
+        assert_eq!(state.to_header(), "congo=t61rcWkgMzE,rojo=00f067aa0ba902b7");
+
+        let state = TraceState::parse("tenant@vendor=value").unwrap();
+        assert_eq!(state.get("tenant@vendor"), Some("value"));
+    }
+
+    #[test]
+    fn ignores_empty_tracestate_members() {
+        let state = TraceState::parse(",a=1,, ,b=2,").unwrap();
+        assert_eq!(state.to_header(), "a=1,b=2");
+
+        assert!(TraceState::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_tracestate() {
+        let too_many = (0..=MAX_TRACESTATE_MEMBERS)
+            .map(|i| format!("k{}=v", i))
+            .collect::<Vec<_>>()
+            .join(",");
+        let invalid = [
+            "a=1,a=2".to_string(),
+            "novalue".to_string(),
+            "a=".to_string(),
+            "=1".to_string(),
+            "Upper=1".to_string(),
+            "_a=1".to_string(),
+            "a=b=c".to_string(),
+            "a=trailing ".to_string(),
+            "a=é".to_string(),
+            "tenant@=1".to_string(),
+            format!("tenant@{}=1", "s".repeat(15)),
+            format!("{}=1", "k".repeat(257)),
+            format!("a={}", "v".repeat(257)),
+            too_many,
+        ];
+        for header in &invalid {
+            assert_eq!(TraceState::parse(header), None, "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn continues_inbound_trace() {
+        let inbound = InboundTrace {
+            newrelic: None,
+            traceparent: Some(traceparent("00", TRACE_ID, PARENT_ID, "01")),
+            tracestate: Some("invalid".to_string()),
+        };
+        let context = TraceContext::from_inbound(Some(&inbound));
+        assert_eq!(context.trace_id(), TRACE_ID);
+        assert_eq!(context.parent().unwrap().parent_id(), PARENT_ID);
+        // An invalid tracestate doesn't discard the traceparent.
+        assert_eq!(context.state(), None);
+
+        let (_, outgoing) = &context.headers()[0];
+        let parent = TraceParent::parse(outgoing).unwrap();
+        assert_eq!(parent.trace_id(), TRACE_ID);
+        assert_ne!(parent.parent_id(), PARENT_ID);
+    }
+
+    #[test]
+    fn starts_new_trace_without_valid_traceparent() {
+        let inbound = InboundTrace {
+            newrelic: None,
+            traceparent: Some("invalid".to_string()),
+            tracestate: None,
+        };
+        let context = TraceContext::from_inbound(Some(&inbound));
+        assert!(context.parent().is_none());
+        assert!(is_id(context.trace_id(), 32));
+        assert!(context.sampled());
+    }
+}
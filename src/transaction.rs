@@ -0,0 +1,325 @@
+use std::{
+    collections::HashSet,
+    error,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use rocket::{
+    request::{self, FromRequest},
+    Outcome, Request,
+};
+
+use crate::{
+    attribute::MAX_CUSTOM_ATTRIBUTES,
+    backend::{Backend, BackendTransaction, SegmentId},
+    error_policy::DEFAULT_PRIORITY,
+    events::{self, EventAttributes, EventError},
+    trace_context::TraceContext,
+    Attribute, AttributeError, ErrorDetail,
+};
+
+/// The New Relic transaction for a request.
+///
+/// Every request gets a transaction, but it is only sent to New Relic
+/// once a handler opts in by using `&Transaction` as a request guard.
+/// Transactions for any other requests are ignored.
+///
+/// Catchers can opt in too, using `request.guard::<&Transaction>()`.
+///
+/// Requests which match no route are named after their status, e.g.
+/// `catcher/404`. However, Rocket runs request guards before parsing path
+/// and query parameters, so a request such as `/users/wp-admin` for
+/// `/users/<id: usize>` is traced by the guard before the route forwards
+/// it. If the 404 catcher then responds, the transaction is still named
+/// after that route, since Rocket doesn't record that it forwarded.
+pub struct Transaction {
+    state: Mutex<State>,
+    // The keys of the custom attributes added so far.
+    custom_attributes: Mutex<HashSet<String>>,
+    // Open segments, innermost last.
+    open_segments: Mutex<Vec<SegmentId>>,
+    trace_context: TraceContext,
+    started: Instant,
+}
+
+pub(crate) enum State {
+    /// A running and traced transaction.
+    /// Will be sent to New Relic.
+    Traced(Box<dyn BackendTransaction>),
+
+    /// A running, but not traced, transaction.
+    /// Will be ignored rather than ended.
+    NotTraced(Box<dyn BackendTransaction>),
+
+    /// A dummy transaction; used if the backend couldn't start
+    /// a transaction, or once the transaction has ended.
+    None,
+}
+
+impl Transaction {
+    // Start a new transaction for a request.
+    pub(crate) fn new(backend: &dyn Backend, name: &str, trace_context: TraceContext) -> Self {
+        // Start a new transaction. Note that this will be ignored
+        // unless it's used in a request guard.
+        let state = backend
+            .start_web_transaction(name)
+            .map(State::NotTraced)
+            .unwrap_or(State::None);
+        Transaction::from_state(state, trace_context)
+    }
+
+    // Start a background transaction, which is always traced.
+    pub(crate) fn background(backend: &dyn Backend, name: &str) -> Self {
+        let state = backend
+            .start_background_transaction(name)
+            .map(State::Traced)
+            .unwrap_or(State::None);
+        Transaction::from_state(state, TraceContext::new())
+    }
+
+    // A dummy transaction, used when the fairing hasn't run.
+    pub(crate) fn none() -> Self {
+        Transaction::from_state(State::None, TraceContext::new())
+    }
+
+    fn from_state(state: State, trace_context: TraceContext) -> Self {
+        Transaction {
+            state: Mutex::new(state),
+            custom_attributes: Mutex::new(HashSet::new()),
+            open_segments: Mutex::new(Vec::new()),
+            trace_context,
+            started: Instant::now(),
+        }
+    }
+
+    // When the transaction started.
+    pub(crate) fn started(&self) -> Instant {
+        self.started
+    }
+
+    /// The W3C trace this transaction is part of.
+    pub fn trace_context(&self) -> &TraceContext {
+        &self.trace_context
+    }
+
+    /// The W3C trace ID, for correlating logs with the transaction.
+    pub fn trace_id(&self) -> &str {
+        self.trace_context.trace_id()
+    }
+
+    /// Whether this transaction will be sent to New Relic.
+    pub fn is_traced(&self) -> bool {
+        matches!(*self.state(), State::Traced(_))
+    }
+
+    /// Add a custom attribute, such as a tenant ID or feature flag.
+    ///
+    /// Keys and string values must be at most 255 bytes long, and each
+    /// transaction can have at most 51 custom attributes, since New Relic
+    /// keeps at most 64 including those added by the fairing. Keys starting
+    /// with the fairing's prefixes, such as `request.` or `http.`, are
+    /// rejected. Adding an attribute again replaces its value.
+    pub fn add_attribute<V: Into<Attribute>>(
+        &self,
+        key: &str,
+        value: V,
+    ) -> Result<(), AttributeError> {
+        let value = value.into();
+        value.validate(key)?;
+        {
+            let mut keys = self
+                .custom_attributes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if !keys.contains(key) {
+                if keys.len() >= MAX_CUSTOM_ATTRIBUTES {
+                    return Err(AttributeError::TooManyAttributes);
+                }
+                keys.insert(key.to_string());
+            }
+        }
+        self.with_transaction(|t| t.add_attribute(key, &value));
+        Ok(())
+    }
+
+    /// Record a custom metric, such as the time taken by a cache lookup.
+    ///
+    /// New Relic requires custom metric names to start with `Custom/`,
+    /// which is added if it's missing.
+    pub fn record_custom_metric(&self, name: &str, value: Duration) {
+        let name = if name.starts_with("Custom/") {
+            name.to_string()
+        } else {
+            format!("Custom/{}", name)
+        };
+        self.with_transaction(|t| t.record_custom_metric(&name, value));
+    }
+
+    /// Record a custom event, such as `CheckoutCompleted`, which can be
+    /// queried alongside the transaction's APM data.
+    ///
+    /// Event types may only contain letters, digits, `:`, `_` and spaces,
+    /// and events can have at most 64 attributes.
+    pub fn record_custom_event(
+        &self,
+        event_type: &str,
+        attributes: &EventAttributes,
+    ) -> Result<(), EventError> {
+        events::validate(event_type, attributes)?;
+        self.with_transaction(|t| t.record_custom_event(event_type, attributes.as_slice()));
+        Ok(())
+    }
+
+    /// Report an error, even if the response succeeds.
+    ///
+    /// The error's type name is used as the class, and the message
+    /// includes the error's sources; see `ErrorDetail::from_error`.
+    /// Boxed errors don't have a useful type name, so report those with
+    /// `notice_error_detail` and a class of their own.
+    pub fn notice_error<E: error::Error + ?Sized>(&self, error: &E) {
+        self.notice_error_with_priority(error, DEFAULT_PRIORITY);
+    }
+
+    /// Like `notice_error`, but with the given priority.
+    pub fn notice_error_with_priority<E: error::Error + ?Sized>(&self, error: &E, priority: i32) {
+        self.notice_error_detail(&ErrorDetail::from_error(error), priority);
+    }
+
+    /// Report an error with the given class and message.
+    pub fn notice_error_detail(&self, detail: &ErrorDetail, priority: i32) {
+        self.with_transaction(|t| t.notice_error(priority, &detail.message, &detail.class));
+    }
+
+    // Run a function on the running transaction, if there is one.
+    pub(crate) fn with_transaction<F: FnOnce(&mut dyn BackendTransaction)>(&self, f: F) {
+        match &mut *self.state() {
+            State::Traced(t) | State::NotTraced(t) => f(&mut **t),
+            State::None => {}
+        }
+    }
+
+    // Lock the stack of open segments.
+    pub(crate) fn open_segments(&self) -> MutexGuard<Vec<SegmentId>> {
+        self.open_segments
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Lock the transaction state.
+    //
+    // A panic while the lock was held can't leave the state half-updated,
+    // so a poisoned lock is safe to recover from.
+    fn state(&self) -> MutexGuard<State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Take the transaction out of the request, leaving a dummy
+    // in its place.
+    pub(crate) fn take(&self) -> State {
+        std::mem::replace(&mut *self.state(), State::None)
+    }
+
+    // Switch the transaction from a NotTraced to a Traced transaction.
+    pub(crate) fn trace(&self) {
+        let mut state = self.state();
+        *state = match std::mem::replace(&mut *state, State::None) {
+            State::NotTraced(t) => State::Traced(t),
+            other => other,
+        };
+    }
+
+    // Switch the transaction from a Traced to a NotTraced transaction,
+    // so that it's ignored even though a request guard used it.
+    pub(crate) fn untrace(&self) {
+        let mut state = self.state();
+        *state = match std::mem::replace(&mut *state, State::None) {
+            State::Traced(t) => State::NotTraced(t),
+            other => other,
+        };
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for &'a Transaction {
+    type Error = ();
+
+    // Mark the transaction as traced, so that it is sent to New Relic.
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let transaction = request.local_cache(Transaction::none);
+        transaction.trace();
+        Outcome::Success(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::RecordingBackend;
+
+    fn finish(backend: &RecordingBackend, transaction: Transaction) -> Vec<(String, Attribute)> {
+        if let State::Traced(transaction) = transaction.take() {
+            transaction.end();
+        }
+        backend.assert_recorded("job").attributes
+    }
+
+    #[test]
+    fn limits_custom_attributes() {
+        let backend = RecordingBackend::new();
+        let transaction = Transaction::background(&backend, "job");
+
+        for i in 0..MAX_CUSTOM_ATTRIBUTES {
+            transaction
+                .add_attribute(&format!("key{}", i), i as i64)
+                .unwrap();
+        }
+        assert_eq!(
+            transaction.add_attribute("one_too_many", 1),
+            Err(AttributeError::TooManyAttributes)
+        );
+
+        assert_eq!(finish(&backend, transaction).len(), MAX_CUSTOM_ATTRIBUTES);
+    }
+
+    #[test]
+    fn replacing_attributes_doesnt_count_towards_limit() {
+        let backend = RecordingBackend::new();
+        let transaction = Transaction::background(&backend, "job");
+
+        for i in 0..MAX_CUSTOM_ATTRIBUTES {
+            transaction.add_attribute("key", i as i64).unwrap();
+        }
+        for i in 1..MAX_CUSTOM_ATTRIBUTES {
+            transaction
+                .add_attribute(&format!("key{}", i), true)
+                .unwrap();
+        }
+        assert_eq!(transaction.add_attribute("key", "last"), Ok(()));
+
+        let attributes = finish(&backend, transaction);
+        assert_eq!(
+            attributes.iter().rev().find(|(k, _)| k == "key"),
+            Some(&("key".to_string(), Attribute::from("last")))
+        );
+    }
+
+    #[test]
+    fn invalid_attributes_arent_added_or_counted() {
+        let backend = RecordingBackend::new();
+        let transaction = Transaction::background(&backend, "job");
+
+        assert_eq!(
+            transaction.add_attribute("http.statusCode", 200),
+            Err(AttributeError::ReservedKey("http.statusCode".to_string()))
+        );
+        assert_eq!(
+            transaction.add_attribute("ratio", f64::NAN),
+            Err(AttributeError::NotFinite("ratio".to_string()))
+        );
+        for i in 0..MAX_CUSTOM_ATTRIBUTES {
+            transaction.add_attribute(&format!("key{}", i), 1).unwrap();
+        }
+
+        assert_eq!(finish(&backend, transaction).len(), MAX_CUSTOM_ATTRIBUTES);
+    }
+}
@@ -0,0 +1,52 @@
+use rocket::{response::Body, Request, Response};
+
+use crate::backend::BackendTransaction;
+
+// Add the standard web request attributes to a transaction.
+//
+// The names match those used by New Relic's own agents, so that they
+// show up in the usual places in APM.
+pub(crate) fn add_attributes(
+    transaction: &mut dyn BackendTransaction,
+    request: &Request,
+    response: &mut Response,
+    include_query_string: bool,
+) {
+    let uri = request.uri();
+    let uri = match uri.query() {
+        Some(query) if include_query_string => format!("{}?{}", uri.path(), query),
+        _ => uri.path().to_string(),
+    };
+    transaction.add_attribute("request.method", &request.method().as_str().into());
+    transaction.add_attribute("request.uri", &uri.into());
+
+    let headers = request.headers();
+    if let Some(host) = headers.get_one("Host") {
+        transaction.add_attribute("request.headers.host", &host.into());
+    }
+    if let Some(user_agent) = headers.get_one("User-Agent") {
+        transaction.add_attribute("request.headers.userAgent", &user_agent.into());
+    }
+    if let Some(content_type) = request.content_type() {
+        transaction.add_attribute(
+            "request.headers.contentType",
+            &content_type.to_string().into(),
+        );
+    }
+    if let Some(length) = content_length(headers.get_one("Content-Length")) {
+        transaction.add_attribute("request.headers.contentLength", &length.into());
+    }
+
+    transaction.add_attribute("http.statusCode", &i64::from(response.status().code).into());
+    let response_length = match response.body() {
+        Some(Body::Sized(_, size)) => Some(size as i64),
+        _ => content_length(response.headers().get_one("Content-Length")),
+    };
+    if let Some(length) = response_length {
+        transaction.add_attribute("response.headers.contentLength", &length.into());
+    }
+}
+
+fn content_length(header: Option<&str>) -> Option<i64> {
+    header.and_then(|length| length.trim().parse().ok())
+}
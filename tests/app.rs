@@ -0,0 +1,114 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+mod common;
+
+use std::time::Duration;
+
+use newrelic_fairing::{
+    backend::{RecordingBackend, TransactionKind},
+    NewRelic, NewRelicApp,
+};
+use rocket::{get, local::Client, routes, State};
+
+use common::client;
+
+#[get("/job")]
+fn job(app: State<NewRelicApp>) -> &'static str {
+    let transaction = app.background_transaction("job");
+    transaction.add_attribute("queue", "default").unwrap();
+    "started"
+}
+
+#[test]
+fn manages_the_app_handle() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![job]);
+
+    let mut response = client.get("/api/job").dispatch();
+
+    assert_eq!(response.body_string(), Some("started".to_string()));
+    let transaction = backend.assert_recorded("job");
+    transaction
+        .assert_ended()
+        .assert_attribute("queue", "default");
+    assert_eq!(transaction.kind, TransactionKind::Background);
+}
+
+#[test]
+fn keeps_the_first_app_handle_when_attached_twice() {
+    let first = RecordingBackend::new();
+    let second = RecordingBackend::new();
+    let rocket = rocket::ignite()
+        .attach(NewRelic::with_backend(first.clone()))
+        .attach(NewRelic::with_backend(second.clone()))
+        .mount("/api", routes![job]);
+    let client = Client::new(rocket).expect("valid Rocket instance");
+
+    client.get("/api/job").dispatch();
+
+    first.assert_recorded("job");
+    second.assert_not_recorded("job");
+}
+
+#[test]
+fn background_transactions_end_when_dropped() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone());
+
+    let transaction = newrelic.app().background_transaction("job");
+    assert!(transaction.is_traced());
+    backend.assert_not_recorded("job");
+    drop(transaction);
+
+    let transaction = backend.assert_recorded("job");
+    transaction.assert_ended();
+    assert_eq!(transaction.kind, TransactionKind::Background);
+}
+
+#[test]
+fn background_transactions_end_when_ended() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone());
+
+    let transaction = newrelic.app().background_transaction("job");
+    transaction.custom_segment("work", "Custom").end();
+    backend.assert_not_recorded("job");
+    transaction.end();
+
+    backend
+        .assert_recorded("job")
+        .assert_ended()
+        .assert_segment("work");
+}
+
+#[test]
+fn prefixes_custom_metric_names() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone());
+
+    let transaction = newrelic.app().background_transaction("job");
+    transaction.record_custom_metric("cache/refresh", Duration::from_millis(12));
+    transaction.record_custom_metric("Custom/cache/hit", Duration::from_millis(1));
+    transaction.end();
+
+    assert_eq!(
+        backend.assert_recorded("job").metrics,
+        [
+            (
+                "Custom/cache/refresh".to_string(),
+                Duration::from_millis(12)
+            ),
+            ("Custom/cache/hit".to_string(), Duration::from_millis(1)),
+        ]
+    );
+}
+
+#[test]
+fn disabled_apps_record_nothing() {
+    let app = NewRelic::disabled().app().clone();
+
+    let transaction = app.background_transaction("job");
+
+    assert!(!app.is_enabled());
+    assert!(!transaction.is_traced());
+}
@@ -0,0 +1,120 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+mod common;
+
+use newrelic_fairing::{
+    backend::RecordingBackend, Attribute, AttributeError, NewRelic, Transaction,
+};
+use rocket::{
+    get,
+    http::{ContentType, Header},
+    post, routes,
+};
+
+use common::client;
+
+#[get("/traced")]
+fn traced(_transaction: &Transaction) -> &'static str {
+    "traced"
+}
+
+#[post("/upload", data = "<body>")]
+fn upload(body: String, _transaction: &Transaction) -> String {
+    body
+}
+
+#[get("/custom")]
+fn custom(transaction: &Transaction) -> &'static str {
+    transaction.add_attribute("tenant", "acme").unwrap();
+    transaction.add_attribute("items", 3).unwrap();
+    transaction.add_attribute("ratio", 0.5).unwrap();
+    transaction.add_attribute("beta", true).unwrap();
+    assert_eq!(
+        transaction.add_attribute("http.statusCode", 999),
+        Err(AttributeError::ReservedKey("http.statusCode".to_string()))
+    );
+    "custom"
+}
+
+#[test]
+fn adds_request_attributes() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![traced]);
+
+    client.get("/api/traced?token=secret").dispatch();
+
+    backend
+        .assert_recorded("GET api/traced")
+        .assert_attribute("request.method", "GET")
+        .assert_attribute("request.uri", "/api/traced")
+        .assert_attribute("http.statusCode", 200)
+        .assert_attribute("response.headers.contentLength", 6);
+}
+
+#[test]
+fn adds_request_header_attributes() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![upload]);
+
+    client
+        .post("/api/upload")
+        .header(Header::new("Host", "example.com"))
+        .header(Header::new("User-Agent", "tests/1.0"))
+        .header(Header::new("Content-Length", "5"))
+        .header(ContentType::Plain)
+        .body("hello")
+        .dispatch();
+
+    backend
+        .assert_recorded("POST api/upload")
+        .assert_attribute("request.headers.host", "example.com")
+        .assert_attribute("request.headers.userAgent", "tests/1.0")
+        .assert_attribute("request.headers.contentType", "text/plain; charset=utf-8")
+        .assert_attribute("request.headers.contentLength", 5);
+}
+
+#[test]
+fn leaves_out_missing_headers() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![traced]);
+
+    client.get("/api/traced").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/traced");
+    assert_eq!(transaction.attribute("request.headers.userAgent"), None);
+    assert_eq!(transaction.attribute("request.headers.contentType"), None);
+    assert_eq!(transaction.attribute("request.headers.contentLength"), None);
+}
+
+#[test]
+fn includes_query_string_when_enabled() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone()).include_query_string(true);
+    let client = client(newrelic, routes![traced]);
+
+    client.get("/api/traced?page=2").dispatch();
+
+    backend
+        .assert_recorded("GET api/traced")
+        .assert_attribute("request.uri", "/api/traced?page=2");
+}
+
+#[test]
+fn adds_custom_attributes() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![custom]);
+
+    client.get("/api/custom").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/custom");
+    transaction
+        .assert_attribute("tenant", "acme")
+        .assert_attribute("items", 3)
+        .assert_attribute("ratio", 0.5)
+        .assert_attribute("beta", true)
+        .assert_attribute("http.statusCode", 200);
+    assert_eq!(
+        transaction.attribute("tenant"),
+        Some(&Attribute::String("acme".to_string()))
+    );
+}
@@ -0,0 +1,9 @@
+use newrelic_fairing::NewRelic;
+use rocket::{local::Client, Route};
+
+// A client for Rocket with the fairing attached, and the given routes
+// mounted at `/api`.
+pub fn client(newrelic: NewRelic, routes: Vec<Route>) -> Client {
+    let rocket = rocket::ignite().attach(newrelic).mount("/api", routes);
+    Client::new(rocket).expect("valid Rocket instance")
+}
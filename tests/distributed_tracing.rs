@@ -0,0 +1,79 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddr};
+
+use newrelic_fairing::{
+    backend::RecordingBackend,
+    distributed_tracing::{InboundTrace, TracePolicy},
+    NewRelic, Transaction,
+};
+use rocket::{get, http::Header, routes};
+
+use common::client;
+
+const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+#[get("/traced")]
+fn traced(transaction: &Transaction) -> String {
+    transaction.trace_id().to_string()
+}
+
+#[test]
+fn continues_inbound_traces() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![traced]);
+
+    let mut response = client
+        .get("/api/traced")
+        .header(Header::new("traceparent", TRACEPARENT))
+        .header(Header::new("tracestate", "a=1"))
+        .dispatch();
+
+    assert_eq!(
+        response.body_string().as_deref(),
+        Some("0af7651916cd43dd8448eb211c80319c")
+    );
+    let transaction = backend.assert_recorded("GET api/traced");
+    assert_eq!(
+        transaction.inbound_trace,
+        Some(InboundTrace {
+            newrelic: None,
+            traceparent: Some(TRACEPARENT.to_string()),
+            tracestate: Some("a=1".to_string()),
+        })
+    );
+}
+
+#[test]
+fn starts_new_traces_without_tracing_headers() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![traced]);
+
+    client.get("/api/traced").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/traced");
+    assert_eq!(transaction.inbound_trace, None);
+}
+
+#[test]
+fn ignores_tracing_headers_from_untrusted_clients() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone())
+        .trace_policy(TracePolicy::trusted(vec![Ipv4Addr::LOCALHOST.into()]));
+    let client = client(newrelic, routes![traced]);
+
+    let mut response = client
+        .get("/api/traced")
+        .remote(SocketAddr::new(Ipv4Addr::new(10, 0, 0, 1).into(), 8000))
+        .header(Header::new("traceparent", TRACEPARENT))
+        .dispatch();
+
+    assert_ne!(
+        response.body_string().as_deref(),
+        Some("0af7651916cd43dd8448eb211c80319c")
+    );
+    let transaction = backend.assert_recorded("GET api/traced");
+    assert_eq!(transaction.inbound_trace, None);
+}
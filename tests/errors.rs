@@ -0,0 +1,290 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+mod common;
+
+use newrelic_fairing::{
+    backend::RecordingBackend, error_policy::ErrorPolicy, NewRelic, Transaction,
+};
+use rocket::{get, http::Status, routes};
+
+use common::client;
+
+#[get("/missing")]
+fn missing(_transaction: &Transaction) -> Status {
+    Status::NotFound
+}
+
+#[get("/gone")]
+fn gone(_transaction: &Transaction) -> Status {
+    Status::NotFound
+}
+
+#[get("/fail")]
+fn fail(_transaction: &Transaction) -> Status {
+    Status::InternalServerError
+}
+
+#[get("/unavailable")]
+fn unavailable(_transaction: &Transaction) -> status::Custom<&'static str> {
+    status::Custom(Status::ServiceUnavailable, " database down\n")
+}
+
+#[get("/invalid")]
+fn invalid(_transaction: &Transaction) -> status::Custom<content::Json<&'static str>> {
+    status::Custom(Status::BadRequest, content::Json(r#"{"error":"invalid"}"#))
+}
+
+#[get("/oversized")]
+fn oversized(_transaction: &Transaction) -> status::Custom<String> {
+    status::Custom(Status::InternalServerError, "x".repeat(2000))
+}
+
+#[get("/binary")]
+fn binary(_transaction: &Transaction) -> status::Custom<Vec<u8>> {
+    status::Custom(Status::InternalServerError, vec![0, 159, 146, 150])
+}
+
+#[derive(Debug)]
+struct LookupError;
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("lookup failed")
+    }
+}
+
+impl error::Error for LookupError {}
+
+impl<'r> Responder<'r> for LookupError {
+    fn respond_to(self, _: &Request) -> response::Result<'r> {
+        Err(Status::BadGateway)
+    }
+}
+
+#[get("/lookup")]
+fn lookup(_transaction: &Transaction) -> Result<&'static str, Reported<LookupError>> {
+    Err(Reported(LookupError))
+}
+
+#[derive(Debug)]
+struct LoadError(LookupError);
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("could not load user")
+    }
+}
+
+impl error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[get("/recovered")]
+fn recovered(transaction: &Transaction) -> &'static str {
+    transaction.notice_error(&LoadError(LookupError));
+    "recovered"
+}
+
+#[get("/boxed")]
+fn boxed(transaction: &Transaction) -> &'static str {
+    let error: Box<dyn error::Error> = Box::new(LookupError);
+    let detail = ErrorDetail {
+        class: "UserLookupError".to_string(),
+        ..ErrorDetail::from_error(&*error)
+    };
+    transaction.notice_error_detail(&detail, 10);
+    "boxed"
+}
+
+fn error_routes() -> Vec<rocket::Route> {
+    routes![
+        fail,
+        unavailable,
+        invalid,
+        oversized,
+        binary,
+        lookup,
+        recovered,
+        boxed
+    ]
+}
+
+#[test]
+fn reports_server_errors_with_priority() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), error_routes());
+
+    client.get("/api/fail").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/fail");
+    transaction.assert_ended().assert_error(500);
+    assert_eq!(transaction.errors[0].class, "HTTP 500");
+    assert_eq!(transaction.errors[0].priority, 100);
+}
+
+#[test]
+fn uses_configured_priorities() {
+    let backend = RecordingBackend::new();
+    let policy = ErrorPolicy::default().priority(400..=499, 20);
+    let newrelic = NewRelic::with_backend(backend.clone()).error_policy(policy);
+    let client = client(newrelic, error_routes());
+
+    client.get("/api/invalid").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/invalid");
+    transaction.assert_error(400);
+    assert_eq!(transaction.errors[0].priority, 20);
+}
+
+#[test]
+fn uses_short_text_bodies_as_messages() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), error_routes());
+
+    let mut response = client.get("/api/unavailable").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/unavailable");
+    transaction.assert_error(503);
+    assert_eq!(transaction.errors[0].message, "database down");
+    // The body is put back for the client.
+    assert_eq!(response.body_string(), Some(" database down\n".to_string()));
+}
+
+#[test]
+fn uses_short_json_bodies_as_messages() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), error_routes());
+
+    let mut response = client.get("/api/invalid").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/invalid");
+    assert_eq!(transaction.errors[0].message, r#"{"error":"invalid"}"#);
+    assert_eq!(
+        response.body_string(),
+        Some(r#"{"error":"invalid"}"#.to_string())
+    );
+}
+
+#[test]
+fn uses_status_for_oversized_bodies() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), error_routes());
+
+    let mut response = client.get("/api/oversized").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/oversized");
+    assert_eq!(transaction.errors[0].message, "500 Internal Server Error");
+    assert_eq!(response.body_string(), Some("x".repeat(2000)));
+}
+
+#[test]
+fn uses_status_for_binary_bodies() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), error_routes());
+
+    let mut response = client.get("/api/binary").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/binary");
+    assert_eq!(transaction.errors[0].message, "500 Internal Server Error");
+    assert_eq!(response.body_bytes(), Some(vec![0, 159, 146, 150]));
+}
+
+#[test]
+fn reported_errors_set_the_class_and_message() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), error_routes());
+
+    client.get("/api/lookup").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/lookup");
+    transaction.assert_error_class(std::any::type_name::<LookupError>());
+    assert_eq!(transaction.errors[0].message, "lookup failed");
+    assert_eq!(transaction.attribute("http.statusCode"), Some(&502.into()));
+}
+
+#[test]
+fn ignores_not_found_by_default() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![missing]);
+
+    client.get("/api/missing").dispatch();
+
+    backend
+        .assert_recorded("GET api/missing")
+        .assert_attribute("http.statusCode", 404)
+        .assert_no_errors();
+}
+
+#[test]
+fn uses_route_policies() {
+    let backend = RecordingBackend::new();
+    let policy = ErrorPolicy::default().route(
+        "missing",
+        ErrorPolicy::empty()
+            .report(404..=404)
+            .priority(404..=404, 80),
+    );
+    let newrelic = NewRelic::with_backend(backend.clone()).error_policy(policy);
+    let client = client(newrelic, routes![missing, gone]);
+
+    client.get("/api/missing").dispatch();
+    client.get("/api/gone").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/missing");
+    transaction.assert_error(404);
+    assert_eq!(transaction.errors[0].priority, 80);
+    backend.assert_recorded("GET api/gone").assert_no_errors();
+}
+
+#[test]
+fn custom_callback_takes_precedence_over_route_policies() {
+    let backend = RecordingBackend::new();
+    let policy = ErrorPolicy::default()
+        .route("missing", ErrorPolicy::empty().report(404..=404))
+        .custom(|request, _| {
+            if request.uri().path() == "/api/missing" {
+                Some(false)
+            } else {
+                None
+            }
+        });
+    let newrelic = NewRelic::with_backend(backend.clone()).error_policy(policy);
+    let client = client(newrelic, routes![missing]);
+
+    client.get("/api/missing").dispatch();
+
+    backend
+        .assert_recorded("GET api/missing")
+        .assert_no_errors();
+}
+
+#[test]
+fn reports_errors_noticed_by_handlers() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), error_routes());
+
+    client.get("/api/recovered").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/recovered");
+    transaction.assert_error_class(std::any::type_name::<LoadError>());
+    assert_eq!(
+        transaction.errors[0].message,
+        "could not load user: lookup failed"
+    );
+    assert!(!transaction.has_error_status(200));
+}
+
+#[test]
+fn reports_boxed_errors_with_a_given_class() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), error_routes());
+
+    client.get("/api/boxed").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/boxed");
+    transaction.assert_error_class("UserLookupError");
+    assert_eq!(transaction.errors[0].message, "lookup failed");
+    assert_eq!(transaction.errors[0].priority, 10);
+}
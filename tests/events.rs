@@ -0,0 +1,99 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+mod common;
+
+use newrelic_fairing::{
+    backend::{RecordingBackend, TransactionKind},
+    events::{EventAttributes, EventError},
+    Attribute, NewRelic, Transaction,
+};
+use rocket::{get, routes};
+
+use common::client;
+
+#[get("/checkout")]
+fn checkout(transaction: &Transaction) -> &'static str {
+    let attributes = EventAttributes::new()
+        .string("plan", "pro")
+        .int("items", 3)
+        .bool("first_order", true);
+    transaction
+        .record_custom_event("CheckoutCompleted", &attributes)
+        .unwrap();
+    assert_eq!(
+        transaction.record_custom_event("Checkout.Completed", &attributes),
+        Err(EventError::InvalidType("Checkout.Completed".to_string()))
+    );
+    "ok"
+}
+
+#[test]
+fn records_events_in_request_transactions() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![checkout]);
+
+    let mut response = client.get("/api/checkout").dispatch();
+
+    assert_eq!(response.body_string(), Some("ok".to_string()));
+    let transaction = backend.assert_recorded("GET api/checkout");
+    transaction.assert_event("CheckoutCompleted");
+    // The invalid event wasn't recorded.
+    assert_eq!(transaction.events.len(), 1);
+    assert_eq!(
+        transaction.events[0].attributes,
+        [
+            ("plan".to_string(), Attribute::from("pro")),
+            ("items".to_string(), Attribute::Int(3)),
+            ("first_order".to_string(), Attribute::Bool(true)),
+        ]
+    );
+}
+
+#[test]
+fn records_app_events_in_background_transactions() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone());
+    let attributes = EventAttributes::new().string("source", "cron");
+
+    newrelic
+        .app()
+        .record_custom_event("CacheRefreshed", &attributes)
+        .unwrap();
+
+    let transaction = backend.assert_recorded("CustomEvent/CacheRefreshed");
+    transaction.assert_ended().assert_event("CacheRefreshed");
+    assert_eq!(transaction.kind, TransactionKind::Background);
+}
+
+#[test]
+fn invalid_app_events_start_no_transaction() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone());
+
+    let result = newrelic
+        .app()
+        .record_custom_event("Cache-Refreshed", &EventAttributes::new());
+
+    assert_eq!(
+        result,
+        Err(EventError::InvalidType("Cache-Refreshed".to_string()))
+    );
+    assert!(backend.transactions().is_empty());
+}
+
+#[test]
+fn records_events_in_existing_background_transactions() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone());
+
+    let transaction = newrelic.app().background_transaction("refresh_cache");
+    transaction
+        .record_custom_event("CacheRefreshed", &EventAttributes::new())
+        .unwrap();
+    transaction.end();
+
+    backend
+        .assert_recorded("refresh_cache")
+        .assert_event("CacheRefreshed");
+    backend.assert_not_recorded("CustomEvent/CacheRefreshed");
+}
@@ -0,0 +1,125 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+mod common;
+
+use newrelic_fairing::{
+    backend::RecordingBackend,
+    naming::{BaseAndHandler, HandlerName, RouteUri},
+    NewRelic, Transaction,
+};
+use rocket::{get, routes, Request, Route};
+
+use common::client;
+
+#[get("/users/<id>")]
+fn get_user(id: usize, _transaction: &Transaction) -> String {
+    format!("user {}", id)
+}
+
+mod items {
+    use newrelic_fairing::Transaction;
+    use rocket::get;
+
+    #[get("/items")]
+    pub fn handle(_transaction: &Transaction) -> &'static str {
+        "list"
+    }
+
+    pub mod create {
+        use newrelic_fairing::Transaction;
+        use rocket::post;
+
+        #[post("/items")]
+        pub fn handle(_transaction: &Transaction) -> &'static str {
+            "create"
+        }
+    }
+}
+
+#[test]
+fn names_transactions_by_method_and_route_by_default() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![get_user]);
+
+    client.get("/api/users/1").dispatch();
+    client.get("/api/users/2").dispatch();
+
+    assert_eq!(backend.find_all("GET api/get_user").len(), 2);
+}
+
+#[test]
+fn separates_methods_with_the_same_handler_name() {
+    let backend = RecordingBackend::new();
+    let client = client(
+        NewRelic::with_backend(backend.clone()),
+        routes![items::handle, items::create::handle],
+    );
+
+    client.get("/api/items").dispatch();
+    client.post("/api/items").dispatch();
+
+    backend.assert_recorded("GET api/handle").assert_ended();
+    backend.assert_recorded("POST api/handle").assert_ended();
+}
+
+#[test]
+fn names_transactions_by_route_uri() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone()).naming(RouteUri);
+    let client = client(newrelic, routes![get_user]);
+
+    client.get("/api/users/1").dispatch();
+
+    backend
+        .assert_recorded("GET /api/users/<id>")
+        .assert_ended();
+}
+
+#[test]
+fn names_transactions_by_handler() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone()).naming(HandlerName);
+    let client = client(newrelic, routes![get_user]);
+
+    client.get("/api/users/1").dispatch();
+
+    backend.assert_recorded("get_user").assert_ended();
+}
+
+#[test]
+fn names_transactions_by_base_and_handler() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone()).naming(BaseAndHandler);
+    let client = client(newrelic, routes![get_user]);
+
+    client.get("/api/users/1").dispatch();
+
+    backend.assert_recorded("api/get_user").assert_ended();
+}
+
+fn method_and_path(route: &Route, request: &Request) -> String {
+    format!("{} {}", route.method, request.uri().path())
+}
+
+#[test]
+fn names_transactions_with_a_function() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone()).naming(method_and_path);
+    let client = client(newrelic, routes![get_user]);
+
+    client.get("/api/users/1").dispatch();
+
+    backend.assert_recorded("GET /api/users/1").assert_ended();
+}
+
+#[test]
+fn names_unmatched_requests_by_status() {
+    let backend = RecordingBackend::new();
+    let client = client(NewRelic::with_backend(backend.clone()), routes![get_user]);
+
+    client.get("/nowhere").dispatch();
+    client.get("/somewhere/else").dispatch();
+
+    assert_eq!(backend.find_all("catcher/404").len(), 2);
+    assert_eq!(backend.transactions().len(), 2);
+}
@@ -0,0 +1,185 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+mod common;
+
+use newrelic_fairing::{
+    backend::RecordingBackend,
+    rules::{Action, Rule},
+    NewRelic, Transaction,
+};
+use rocket::{
+    get,
+    handler::Outcome,
+    http::{Header, Method},
+    local::Client,
+    routes, Data, Request, Route,
+};
+
+use common::client;
+
+#[get("/plain")]
+fn plain() -> &'static str {
+    "plain"
+}
+
+#[get("/traced")]
+fn traced(_transaction: &Transaction) -> &'static str {
+    "traced"
+}
+
+#[get("/health")]
+fn health(_transaction: &Transaction) -> &'static str {
+    "ok"
+}
+
+// An unnamed route, like those `StaticFiles` creates, which uses the
+// transaction guard so that only the rules can ignore it.
+fn static_files() -> Route {
+    fn handler<'r>(request: &'r Request, _: Data) -> Outcome<'r> {
+        let _ = request.guard::<&Transaction>();
+        Outcome::from(request, "file")
+    }
+    Route::new(Method::Get, "/static/<path..>", handler)
+}
+
+// A client with `health` mounted at the root, where the default
+// exclusions look for it.
+fn health_client(newrelic: NewRelic) -> Client {
+    let rocket = rocket::ignite()
+        .attach(newrelic)
+        .mount("/", routes![health]);
+    Client::new(rocket).expect("valid Rocket instance")
+}
+
+#[test]
+fn trace_rules_trace_routes_without_the_guard() {
+    let backend = RecordingBackend::new();
+    let newrelic =
+        NewRelic::with_backend(backend.clone()).rule(Rule::new(Action::Trace).header("X-Debug"));
+    let client = client(newrelic, routes![plain, traced]);
+
+    client.get("/api/plain").dispatch();
+    backend.assert_recorded("GET api/plain").assert_ignored();
+
+    backend.clear();
+    client
+        .get("/api/plain")
+        .header(Header::new("X-Debug", "1"))
+        .dispatch();
+    backend.assert_recorded("GET api/plain").assert_ended();
+}
+
+#[test]
+fn ignore_rules_skip_transactions_before_routing() {
+    let backend = RecordingBackend::new();
+    let newrelic =
+        NewRelic::with_backend(backend.clone()).rule(Rule::new(Action::Ignore).path("/api/traced"));
+    let client = client(newrelic, routes![plain, traced]);
+
+    let mut response = client.get("/api/traced").dispatch();
+
+    // The guard still works, but no transaction is started.
+    assert_eq!(response.body_string(), Some("traced".to_string()));
+    assert!(backend.transactions().is_empty());
+}
+
+#[test]
+fn route_rules_are_applied_once_routed() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone())
+        .rule(Rule::new(Action::Ignore).route("traced"))
+        .rule(Rule::new(Action::Trace).route("plain"));
+    let client = client(newrelic, routes![plain, traced]);
+
+    client.get("/api/traced").dispatch();
+    backend.assert_recorded("GET api/traced").assert_ignored();
+
+    client.get("/api/plain").dispatch();
+    backend.assert_recorded("GET api/plain").assert_ended();
+}
+
+#[test]
+fn sample_rules_trace_the_given_fraction() {
+    let backend = RecordingBackend::new();
+    let newrelic = NewRelic::with_backend(backend.clone())
+        .rule(Rule::new(Action::Sample(1.0)).path("/api/plain"))
+        .rule(Rule::new(Action::Sample(0.0)).path("/api/traced"));
+    let client = client(newrelic, routes![plain, traced]);
+
+    client.get("/api/plain").dispatch();
+    client.get("/api/traced").dispatch();
+
+    backend.assert_recorded("GET api/plain").assert_ended();
+    backend.assert_not_recorded("GET api/traced");
+}
+
+#[test]
+fn ignores_health_checks_by_default() {
+    let backend = RecordingBackend::new();
+    let client = health_client(NewRelic::with_backend(backend.clone()));
+
+    let mut response = client.get("/health").dispatch();
+
+    assert_eq!(response.body_string(), Some("ok".to_string()));
+    assert!(backend.transactions().is_empty());
+}
+
+#[test]
+fn health_checks_can_be_traced() {
+    let backend = RecordingBackend::new();
+    let client = health_client(NewRelic::with_backend(backend.clone()).default_exclusions(false));
+    client.get("/health").dispatch();
+    backend.assert_recorded("GET /health").assert_ended();
+
+    let backend = RecordingBackend::new();
+    let client = health_client(
+        NewRelic::with_backend(backend.clone()).rule(Rule::new(Action::Trace).path("/health")),
+    );
+    client.get("/health").dispatch();
+    backend.assert_recorded("GET /health").assert_ended();
+}
+
+#[test]
+fn ignores_unnamed_routes_by_default() {
+    let backend = RecordingBackend::new();
+    let mut routes = routes![traced];
+    routes.push(static_files());
+    let client = client(NewRelic::with_backend(backend.clone()), routes);
+
+    let mut response = client.get("/api/static/app.js").dispatch();
+
+    assert_eq!(response.body_string(), Some("file".to_string()));
+    backend
+        .assert_recorded("GET api/unknown_handler")
+        .assert_ignored();
+
+    // Named routes aren't affected.
+    client.get("/api/traced").dispatch();
+    backend.assert_recorded("GET api/traced").assert_ended();
+}
+
+#[test]
+fn unnamed_routes_can_be_traced() {
+    let backend = RecordingBackend::new();
+    client(
+        NewRelic::with_backend(backend.clone()).default_exclusions(false),
+        vec![static_files()],
+    )
+    .get("/api/static/app.js")
+    .dispatch();
+    backend
+        .assert_recorded("GET api/unknown_handler")
+        .assert_ended();
+
+    let backend = RecordingBackend::new();
+    client(
+        NewRelic::with_backend(backend.clone())
+            .rule(Rule::new(Action::Trace).path("/api/static/**")),
+        vec![static_files()],
+    )
+    .get("/api/static/app.js")
+    .dispatch();
+    backend
+        .assert_recorded("GET api/unknown_handler")
+        .assert_ended();
+}
@@ -0,0 +1,88 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+mod common;
+
+use newrelic_fairing::{
+    backend::{RecordingBackend, SegmentParams},
+    NewRelic, Transaction,
+};
+use rocket::{get, routes};
+
+use common::client;
+
+#[get("/page")]
+fn page(_transaction: &Transaction) -> &'static str {
+    "page"
+}
+
+#[get("/empty")]
+fn empty(_transaction: &Transaction) {}
+
+fn timed(backend: &RecordingBackend) -> NewRelic {
+    NewRelic::with_backend(backend.clone()).time_body_send(true)
+}
+
+#[test]
+fn ends_transactions_after_the_body_is_sent() {
+    let backend = RecordingBackend::new();
+    let client = client(timed(&backend), routes![page, empty]);
+
+    let mut response = client.get("/api/page").dispatch();
+
+    backend.assert_not_recorded("GET api/page");
+    // Reading the body consumes it, ending the transaction.
+    assert_eq!(response.body_string(), Some("page".to_string()));
+    let transaction = backend.assert_recorded("GET api/page");
+    transaction.assert_ended().assert_segment("send body");
+    assert!(transaction.attribute("timing.handler").is_some());
+    assert!(transaction.attribute("timing.bodySend").is_some());
+    let segment = transaction.segment("send body").unwrap();
+    assert_eq!(
+        segment.params,
+        SegmentParams::Custom {
+            name: "send body".to_string(),
+            category: "Response".to_string(),
+        }
+    );
+    assert!(segment.duration.is_some());
+}
+
+#[test]
+fn ends_transactions_without_a_body_immediately() {
+    let backend = RecordingBackend::new();
+    let client = client(timed(&backend), routes![page, empty]);
+
+    let _response = client.get("/api/empty").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/empty");
+    transaction.assert_ended().assert_segment("send body");
+    assert!(transaction.attribute("timing.bodySend").is_some());
+}
+
+#[test]
+fn ends_head_requests_immediately() {
+    let backend = RecordingBackend::new();
+    let client = client(timed(&backend), routes![page, empty]);
+
+    let _response = client.head("/api/page").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/page");
+    transaction.assert_ended().assert_segment("send body");
+    assert!(transaction.attribute("timing.bodySend").is_some());
+}
+
+#[test]
+fn doesnt_time_body_send_by_default() {
+    let backend = RecordingBackend::new();
+    let client = client(
+        NewRelic::with_backend(backend.clone()),
+        routes![page, empty],
+    );
+
+    let _response = client.get("/api/page").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/page");
+    assert!(transaction.attribute("timing.handler").is_some());
+    assert!(transaction.attribute("timing.bodySend").is_none());
+    assert!(transaction.segment("send body").is_none());
+}
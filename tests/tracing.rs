@@ -0,0 +1,103 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+mod common;
+
+use newrelic_fairing::{
+    backend::{RecordingBackend, TransactionKind},
+    Config, NewRelic, Transaction,
+};
+use rocket::{get, http::Status, routes};
+
+use common::client;
+
+#[get("/untraced")]
+fn untraced() -> &'static str {
+    "untraced"
+}
+
+#[get("/traced")]
+fn traced(transaction: &Transaction) -> &'static str {
+    assert!(transaction.is_traced());
+    "traced"
+}
+
+#[test]
+fn ignores_requests_without_the_guard() {
+    let backend = RecordingBackend::new();
+    let client = client(
+        NewRelic::with_backend(backend.clone()),
+        routes![untraced, traced],
+    );
+
+    client.get("/api/untraced").dispatch();
+
+    backend.assert_recorded("GET api/untraced").assert_ignored();
+}
+
+#[test]
+fn traces_requests_using_the_guard() {
+    let backend = RecordingBackend::new();
+    let client = client(
+        NewRelic::with_backend(backend.clone()),
+        routes![untraced, traced],
+    );
+
+    client.get("/api/traced").dispatch();
+
+    let transaction = backend.assert_recorded("GET api/traced");
+    transaction.assert_ended().assert_no_errors();
+    assert_eq!(transaction.kind, TransactionKind::Web);
+    assert_eq!(backend.transactions().len(), 1);
+}
+
+#[test]
+fn ends_transactions_before_the_body_is_read() {
+    let backend = RecordingBackend::new();
+    let client = client(
+        NewRelic::with_backend(backend.clone()),
+        routes![untraced, traced],
+    );
+
+    let mut response = client.get("/api/traced").dispatch();
+
+    backend.assert_recorded("GET api/traced").assert_ended();
+    assert_eq!(response.body_string(), Some("traced".to_string()));
+    assert_eq!(backend.transactions().len(), 1);
+}
+
+#[test]
+fn ignores_untraced_transactions_before_the_body_is_read() {
+    let backend = RecordingBackend::new();
+    let client = client(
+        NewRelic::with_backend(backend.clone()),
+        routes![untraced, traced],
+    );
+
+    let mut response = client.get("/api/untraced").dispatch();
+
+    backend.assert_recorded("GET api/untraced").assert_ignored();
+    assert_eq!(response.body_string(), Some("untraced".to_string()));
+}
+
+#[test]
+fn disabled_fairing_still_serves_guarded_routes() {
+    let newrelic = NewRelic::disabled();
+    assert!(!newrelic.is_enabled());
+    let client = client(newrelic, routes![untraced, traced]);
+
+    let mut response = client.get("/api/traced").dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string(), Some("traced".to_string()));
+}
+
+#[test]
+fn disabled_config_creates_disabled_fairing() {
+    let mut config = Config::new("my-service", "invalid");
+    config.enabled = false;
+
+    let newrelic = NewRelic::from_config(&config).unwrap();
+
+    assert!(!newrelic.is_enabled());
+    assert!(!newrelic.app().is_enabled());
+}